@@ -0,0 +1,49 @@
+#[macro_use]
+extern crate criterion;
+extern crate rand_123;
+
+use criterion::{black_box, Criterion};
+
+use rand_123::distributions::{Exp, Normal, Sample, ZigguratExp, ZigguratNormal};
+use rand_123::philox::Philox4x64;
+use rand_123::rng::{Philox4x64Rng, ThreeFry2x64Rng};
+use rand_123::threefry::ThreeFry2x64;
+
+const BATCH: usize = 1000;
+
+macro_rules! bench_rng {
+    ($c: expr, $name: expr, $rng: expr) => {{
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let exp = Exp::new(1.0).unwrap();
+        let zn = ZigguratNormal::new();
+        let ze = ZigguratExp::new();
+        let mut rng = $rng;
+        $c.bench_function(&format!("{}/normal/box_muller", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| normal.sample(&mut rng)).sum::<f64>()
+        }));
+        $c.bench_function(&format!("{}/normal/ziggurat_f64", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| zn.sample(&mut rng)).sum::<f64>()
+        }));
+        $c.bench_function(&format!("{}/normal/ziggurat_f32", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| zn.sample_f32(&mut rng)).sum::<f32>()
+        }));
+        $c.bench_function(&format!("{}/exp/inversion", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| exp.sample(&mut rng)).sum::<f64>()
+        }));
+        $c.bench_function(&format!("{}/exp/ziggurat_f64", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| ze.sample(&mut rng)).sum::<f64>()
+        }));
+        $c.bench_function(&format!("{}/exp/ziggurat_f32", $name), |b| b.iter(|| {
+            (0..BATCH).map(|_| ze.sample_f32(&mut rng)).sum::<f32>()
+        }));
+        black_box(&mut rng);
+    }}
+}
+
+fn normal_and_exp(c: &mut Criterion) {
+    bench_rng!(c, "philox4x64", Philox4x64Rng::from(Philox4x64::from_seed([1; 16])));
+    bench_rng!(c, "threefry2x64", ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([1; 16])));
+}
+
+criterion_group!(benches, normal_and_exp);
+criterion_main!(benches);
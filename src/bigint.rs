@@ -0,0 +1,144 @@
+//! Uniform arbitrary-precision integers, with the `bigint` feature.
+//!
+//! `below_biguint` draws by rejection with a fixed word order, so results
+//! for a given generator state never change between releases. For a bound
+//! `b > 1`, let `k` be the bit length of `b - 1`. Each attempt takes
+//! `ceil(k / 64)` words from `next_u64`, least significant first, keeps
+//! only the low `k - 64 * (ceil(k / 64) - 1)` bits of the last one, and is
+//! accepted if the result is below `b`. An attempt succeeds with
+//! probability above 1/2, so a draw takes fewer than `2 * ceil(k / 64)`
+//! words on average. A bound of 1 takes no words.
+
+use num_bigint::BigUint;
+use rand_core::RngCore;
+
+use super::philox::{Array2x32, Array4x32};
+use super::rng::CounterRng;
+
+pub fn below_biguint<R: RngCore + ?Sized>(rng: &mut R, bound: &BigUint) -> BigUint {
+    assert!(bound.bits() > 0, "bound must be positive");
+    let k = (bound - 1u32).bits() as usize;
+    let words = k.div_ceil(64);
+    let mask = u64::MAX >> (64 * words - k);
+    loop {
+        let mut digits = Vec::with_capacity(2 * words);
+        for w in 0..words {
+            let mut x = rng.next_u64();
+            if w == words - 1 {
+                x &= mask;
+            }
+            digits.push(x as u32);
+            digits.push((x >> 32) as u32);
+        }
+        let x = BigUint::new(digits);
+        if x < *bound {
+            return x;
+        }
+    }
+}
+
+/// Uniform in `[low, high)`.
+pub fn range_biguint<R: RngCore + ?Sized>(rng: &mut R, low: &BigUint, high: &BigUint) -> BigUint {
+    assert!(low < high, "empty range {}..{}", low, high);
+    low + below_biguint(rng, &(high - low))
+}
+
+/// `below_biguint` as a pure function of a Philox-4x32 key and counter,
+/// drawing from `CounterRng`.
+pub fn below_biguint_at(key: Array2x32, ctr: Array4x32, bound: &BigUint) -> BigUint {
+    below_biguint(&mut CounterRng::new(key, ctr), bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+    use rand_core::RngCore;
+
+    use super::{below_biguint, range_biguint, below_biguint_at};
+    use distributions::chi_square_test;
+    use philox::Philox4x32;
+    use rng::{CounterRng, Philox4x32Rng, ThreeFry2x64Rng};
+    use threefry::ThreeFry2x64;
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([4, 0, 0, 0, 1, 0, 0, 0]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([6; 16]))
+    }
+
+    fn big(x: u128) -> BigUint {
+        BigUint::from(x)
+    }
+
+    #[test]
+    fn uniform() {
+        let mut rng = philox();
+        let bound = big(10);
+        let counts: Vec<u64> = (0..20000)
+            .map(|_| below_biguint(&mut rng, &bound).to_u64_digits().first().cloned().unwrap_or(0))
+            .collect();
+        chi_square_test(&counts, |_| 0.1);
+
+        // with the top word unmasked or taken modulo, values below 2^64
+        // would not come up a third of the time
+        let mut rng = threefry();
+        let bound = big(3 << 64);
+        let trials = 30000;
+        let low = (0..trials).filter(|_| below_biguint(&mut rng, &bound) < big(1 << 64)).count();
+        let p = low as f64 / trials as f64;
+        assert!((p - 1.0 / 3.0).abs() < 0.015, "{}", p);
+
+        let bound = BigUint::from(7u32).pow(200);
+        for _ in 0..100 {
+            assert!(below_biguint(&mut rng, &bound) < bound);
+        }
+        let x = range_biguint(&mut rng, &big(1 << 100), &big((1 << 100) + 5));
+        assert!(x >= big(1 << 100) && x < big((1 << 100) + 5));
+    }
+
+    #[test]
+    fn word_consumption() {
+        // a power-of-two bound never rejects: 2^128 takes two whole words
+        let mut rng = threefry();
+        let mut other = rng.clone();
+        let x = below_biguint(&mut rng, &(BigUint::from(1u32) << 128));
+        let lo = other.next_u64() as u128;
+        let hi = other.next_u64() as u128;
+        assert_eq!(x, big(hi << 64 | lo));
+        assert_eq!(rng.next_u64(), other.next_u64());
+
+        // 2^65 masks its second word to one bit
+        let x = below_biguint(&mut rng, &big(1 << 65));
+        let lo = other.next_u64() as u128;
+        let hi = (other.next_u64() & 1) as u128;
+        assert_eq!(x, big(hi << 64 | lo));
+
+        // a bound of one takes nothing
+        assert_eq!(below_biguint(&mut rng, &big(1)), big(0));
+        assert_eq!(rng.next_u64(), other.next_u64());
+    }
+
+    #[test]
+    fn counter_based() {
+        let key = [8, 9];
+        let ctr = [1, 2, 3, 4];
+        let bound = BigUint::from(10u32).pow(40);
+        assert_eq!(below_biguint_at(key, ctr, &bound), below_biguint(&mut CounterRng::new(key, ctr), &bound));
+        assert!(below_biguint_at(key, ctr, &bound) != below_biguint_at(key, [2, 2, 3, 4], &bound));
+    }
+
+    #[test]
+    #[should_panic(expected = "bound must be positive")]
+    fn zero_bound() {
+        below_biguint(&mut philox(), &big(0));
+    }
+
+    #[test]
+    fn golden_values() {
+        let bound = BigUint::from(10u32).pow(40);
+        assert_eq!(below_biguint(&mut philox(), &bound).to_string(), "1719244271388433871688823361284004149686");
+        assert_eq!(below_biguint_at([0xdead, 0xbeef], [0; 4], &big(1000)), big(77));
+    }
+}
@@ -0,0 +1,195 @@
+//! Wiener paths on a dyadic grid by Brownian-bridge construction.
+//!
+//! Node 0 gives the endpoint `W(T)`, and the interval `j` of level `l`,
+//! `[j T / 2^l, (j + 1) T / 2^l]`, has node `2^l + j` (heap order), which
+//! gives its midpoint from its endpoints. Each node's normal is the inverse
+//! normal CDF of the first word of `philox_4x64` at counter `[node, path, 0,
+//! 0]`, so any grid point can be evaluated alone in `O(level)` and every
+//! resolution, chunking or worker split sees the same path.
+
+use super::distributions::normal_quantile;
+use super::philox::{philox_4x64, Array2x64};
+use super::u01::u01_open_f64;
+
+/// Deepest supported grid level; node ids must fit in 64 bits.
+pub const MAX_LEVEL: u32 = 63;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrownianBridge {
+    key: Array2x64,
+    path: u64,
+    horizon: f64,
+}
+
+impl BrownianBridge {
+    /// Path number `path` under `key` on `[0, horizon]`.
+    pub fn new(key: Array2x64, path: u64, horizon: f64) -> Self {
+        assert!(horizon > 0.0 && horizon.is_finite(), "horizon must be positive, got {}", horizon);
+        Self { key, path, horizon }
+    }
+
+    pub fn horizon(&self) -> f64 {
+        self.horizon
+    }
+
+    /// Standard normal of a node.
+    fn normal(&self, node: u64) -> f64 {
+        normal_quantile(u01_open_f64(philox_4x64([node, self.path, 0, 0], self.key)[0]))
+    }
+
+    /// Time of grid point `k` at `level`.
+    pub fn time(&self, level: u32, k: u64) -> f64 {
+        self.horizon * k as f64 / 2f64.powi(level as i32)
+    }
+
+    /// `W(k T / 2^level)`, for `k` up to `2^level`.
+    pub fn at(&self, level: u32, k: u64) -> f64 {
+        assert!(level <= MAX_LEVEL, "level {} is deeper than {}", level, MAX_LEVEL);
+        assert!(k as u128 <= 1u128 << level, "grid point {} is past the end of level {}", k, level);
+        let end = self.horizon.sqrt() * self.normal(0);
+        if k == 0 {
+            return 0.0;
+        }
+        if k as u128 == 1u128 << level {
+            return end;
+        }
+        // descend through the intervals containing the point, narrowing
+        // [a, b] until the point is an endpoint
+        let (mut wa, mut wb) = (0.0, end);
+        let mut width = self.horizon;
+        for l in 0..level {
+            let shift = level - l;
+            let (j, offset) = (k >> shift, k & ((1u64 << shift) - 1));
+            if offset == 0 {
+                return wa;
+            }
+            let mid = 0.5 * (wa + wb) + (0.25 * width).sqrt() * self.normal((1u64 << l) + j);
+            width *= 0.5;
+            if offset >> (shift - 1) == 0 {
+                wb = mid;
+            } else {
+                wa = mid;
+            }
+        }
+        // the last midpoint was the point itself
+        wa
+    }
+
+    /// `W` at grid points `start..start + out.len()` of `level`.
+    pub fn fill(&self, level: u32, start: u64, out: &mut [f64]) {
+        for (i, w) in out.iter_mut().enumerate() {
+            *w = self.at(level, start + i as u64);
+        }
+    }
+
+    /// All `2^level + 1` grid values of `level`, refining one level at a time.
+    pub fn path(&self, level: u32) -> Vec<f64> {
+        assert!(level < 32, "level {} is too deep to build whole", level);
+        let mut w = vec![0.0, self.horizon.sqrt() * self.normal(0)];
+        let mut width = self.horizon;
+        for l in 0..level {
+            let sd = (0.25 * width).sqrt();
+            let mut next = Vec::with_capacity(2 * w.len() - 1);
+            for j in 0..w.len() - 1 {
+                next.push(w[j]);
+                next.push(0.5 * (w[j] + w[j + 1]) + sd * self.normal((1u64 << l) + j as u64));
+            }
+            next.push(w[w.len() - 1]);
+            w = next;
+            width *= 0.5;
+        }
+        w
+    }
+
+    /// The `2^level` increments `W(t_{k+1}) - W(t_k)` of the random walk
+    /// on `level`.
+    pub fn increments(&self, level: u32) -> Vec<f64> {
+        self.path(level).windows(2).map(|w| w[1] - w[0]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BrownianBridge;
+    use distributions::{ks_test, normal_cdf};
+
+    const KEY: [u64; 2] = [0x5eed, 0xb41d6e];
+
+    #[test]
+    fn random_access() {
+        let b = BrownianBridge::new(KEY, 3, 2.0);
+        let path = b.path(8);
+        assert_eq!(path.len(), 257);
+        assert_eq!(path[0], 0.0);
+        for k in 0..257 {
+            assert_eq!(b.at(8, k), path[k as usize]);
+            // refining the grid keeps every existing point
+            assert_eq!(b.at(11, 8 * k), path[k as usize]);
+        }
+        let coarse = b.path(5);
+        for k in 0..33 {
+            assert_eq!(coarse[k], path[8 * k]);
+        }
+        let mut chunk = [0.0; 50];
+        b.fill(8, 100, &mut chunk);
+        assert_eq!(&chunk[..], &path[100..150]);
+        // deep levels stay cheap
+        let deep = b.at(60, 3 << 57);
+        assert_eq!(deep, path[96]);
+        assert!(b.at(63, (1 << 63) - 1).is_finite());
+    }
+
+    #[test]
+    fn distinct_paths() {
+        let a = BrownianBridge::new(KEY, 0, 1.0).path(4);
+        let b = BrownianBridge::new(KEY, 1, 1.0).path(4);
+        assert!(a.iter().zip(&b).skip(1).all(|(x, y)| x != y));
+    }
+
+    #[test]
+    fn marginals() {
+        // W(t) / sqrt(t) is standard normal at every grid point
+        let n = 5000;
+        for &(level, k) in [(0u32, 1u64), (3, 3), (6, 41)].iter() {
+            let t = 4.0 * k as f64 / (1u64 << level) as f64;
+            let samples = (0..n)
+                .map(|p| BrownianBridge::new(KEY, p, 4.0).at(level, k) / t.sqrt())
+                .collect();
+            ks_test(samples, normal_cdf);
+        }
+    }
+
+    #[test]
+    fn independent_increments() {
+        let n = 4000;
+        let (mut sum, mut sq, mut cross) = ([0.0; 8], [0.0; 8], 0.0);
+        for p in 0..n {
+            let d = BrownianBridge::new(KEY, p, 1.0).increments(3);
+            for i in 0..8 {
+                sum[i] += d[i];
+                sq[i] += d[i] * d[i];
+            }
+            cross += d[2] * d[5];
+        }
+        let n = n as f64;
+        for i in 0..8 {
+            assert!((sum[i] / n).abs() < 0.03, "{}", sum[i] / n);
+            // each increment has variance 1/8
+            assert!((sq[i] / n - 0.125).abs() < 0.012, "{}", sq[i] / n);
+        }
+        assert!((cross / n).abs() < 0.01, "{}", cross / n);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn past_the_end() {
+        BrownianBridge::new(KEY, 0, 1.0).at(3, 9);
+    }
+
+    #[test]
+    fn golden_values() {
+        let b = BrownianBridge::new(KEY, 7, 1.0);
+        assert_eq!(b.path(2), [0.0, 0.10267679849303796, 0.7006602100991935,
+                             0.8480614254355844, 1.7515508332372625]);
+    }
+}
@@ -0,0 +1,362 @@
+use std::cmp::Ordering;
+
+/// What a generator does once its counter range is used up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WrapPolicy {
+    /// Start again from the beginning of the range.
+    #[default]
+    Wrap,
+    /// Panic instead of generating a block outside the range.
+    Panic,
+    /// Make `try_fill_bytes` return an error instead of leaving the range.
+    /// Infallible methods such as `next_u32` panic.
+    Error,
+}
+
+/// A multi-word counter, least significant word first.
+pub trait Counter: Copy + Eq {
+    /// Adds one, returning true if the counter wrapped around to zero.
+    fn increment(&mut self) -> bool;
+    /// Widens the counter to 256 bits.
+    fn to_u256(self) -> [u64; 4];
+    /// Truncates a 256-bit value to the counter width.
+    fn from_u256(words: [u64; 4]) -> Self;
+
+    fn from_u128(n: u128) -> Self {
+        Self::from_u256([n as u64, (n >> 64) as u64, 0, 0])
+    }
+
+    /// Adds `n`, returning the wrapped sum and whether it overflowed.
+    fn overflowing_add(self, n: u128) -> (Self, bool) {
+        let a = self.to_u256();
+        let b = [n as u64, (n >> 64) as u64, 0, 0];
+        let mut sum = [0u64; 4];
+        let mut carry = false;
+        for i in 0..4 {
+            let (s, c1) = a[i].overflowing_add(b[i]);
+            let (s, c2) = s.overflowing_add(carry as u64);
+            sum[i] = s;
+            carry = c1 || c2;
+        }
+        let wrapped = Self::from_u256(sum);
+        (wrapped, carry || wrapped.to_u256() != sum)
+    }
+
+    fn cmp_counter(&self, other: &Self) -> Ordering {
+        let a = self.to_u256();
+        let b = other.to_u256();
+        a.iter().rev().cmp(b.iter().rev())
+    }
+
+    /// Number of increments from `self` up to `end`, saturating at
+    /// `u128::MAX` and zero if `end` is not above `self`.
+    fn distance_to(&self, end: &Self) -> u128 {
+        if self.cmp_counter(end) != Ordering::Less {
+            return 0;
+        }
+        let a = self.to_u256();
+        let b = end.to_u256();
+        let mut diff = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (d, b1) = b[i].overflowing_sub(a[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u64);
+            diff[i] = d;
+            borrow = b1 || b2;
+        }
+        if diff[2] != 0 || diff[3] != 0 {
+            u128::MAX
+        } else {
+            diff[0] as u128 | (diff[1] as u128) << 64
+        }
+    }
+
+    /// Number of increments left before the counter wraps, saturating at
+    /// `u128::MAX`.
+    fn distance_to_wrap(&self) -> u128 {
+        let max = Self::from_u256([u64::MAX; 4]);
+        self.distance_to(&max).saturating_add(1)
+    }
+}
+
+macro_rules! impl_counter {
+    ($w: ty, $n: expr) => {
+        impl Counter for [$w; $n] {
+            #[inline]
+            fn increment(&mut self) -> bool {
+                for word in self.iter_mut() {
+                    *word = word.wrapping_add(1);
+                    if *word != 0 {
+                        return false;
+                    }
+                }
+                true
+            }
+
+            fn to_u256(self) -> [u64; 4] {
+                let bits = 8 * std::mem::size_of::<$w>();
+                let mut words = [0u64; 4];
+                for (i, &w) in self.iter().enumerate() {
+                    words[i * bits / 64] |= (w as u64) << (i * bits % 64);
+                }
+                words
+            }
+
+            fn from_u256(words: [u64; 4]) -> Self {
+                let bits = 8 * std::mem::size_of::<$w>();
+                let mut ctr = [0 as $w; $n];
+                for (i, w) in ctr.iter_mut().enumerate() {
+                    *w = (words[i * bits / 64] >> (i * bits % 64)) as $w;
+                }
+                ctr
+            }
+        }
+    }
+}
+
+impl_counter!(u32, 2);
+impl_counter!(u32, 4);
+impl_counter!(u64, 2);
+impl_counter!(u64, 4);
+
+/// Counter of a generator core, together with the range it may use and
+/// what to do when that range runs out.
+#[derive(Clone, Debug)]
+pub(crate) struct CounterState<C> {
+    ctr: C,
+    start: C,
+    limit: Option<C>,
+    policy: WrapPolicy,
+    wrapped: bool,
+}
+
+impl<C: Counter> CounterState<C> {
+    pub fn new(ctr: C) -> Self {
+        Self { ctr, start: ctr, limit: None, policy: WrapPolicy::Wrap, wrapped: false }
+    }
+
+    /// Returns the counter for the next block and advances past it.
+    #[inline]
+    pub fn take(&mut self) -> C {
+        if self.exhausted() {
+            match self.policy {
+                WrapPolicy::Wrap => {
+                    if let Some(ref limit) = self.limit {
+                        // a limit at or below the start leaves nothing to wrap to
+                        assert!(self.start.cmp_counter(limit) == Ordering::Less,
+                                "counter range is empty");
+                        self.ctr = self.start;
+                    }
+                    self.wrapped = false;
+                }
+                WrapPolicy::Panic | WrapPolicy::Error => panic!("counter range exhausted"),
+            }
+        }
+        let ctr = self.ctr;
+        self.wrapped = self.ctr.increment();
+        ctr
+    }
+
+    fn exhausted(&self) -> bool {
+        self.wrapped || match self.limit {
+            Some(ref limit) => self.ctr.cmp_counter(limit) != Ordering::Less,
+            None => false,
+        }
+    }
+
+    pub fn get(&self) -> C {
+        self.ctr
+    }
+
+    pub fn set(&mut self, ctr: C) {
+        self.ctr = ctr;
+        self.start = ctr;
+        self.wrapped = false;
+    }
+
+    pub fn limit(&self) -> Option<C> {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: Option<C>) {
+        self.limit = limit;
+    }
+
+    pub fn policy(&self) -> WrapPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: WrapPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn remaining(&self) -> u128 {
+        if self.wrapped {
+            return 0;
+        }
+        match self.limit {
+            Some(ref limit) => self.ctr.distance_to(limit),
+            None => self.ctr.distance_to_wrap(),
+        }
+    }
+}
+
+macro_rules! impl_counter_access {
+    ($t: ty, $ctr: ty) => {
+        impl $t {
+            /// Counter of the next block to be generated.
+            pub fn counter(&self) -> $ctr {
+                self.ctr.get()
+            }
+
+            /// Moves the stream to `ctr`. Under `WrapPolicy::Wrap` a stream
+            /// with a counter limit returns here when it reaches the limit.
+            pub fn set_counter(&mut self, ctr: $ctr) {
+                self.ctr.set(ctr)
+            }
+
+            pub fn counter_limit(&self) -> Option<$ctr> {
+                self.ctr.limit()
+            }
+
+            /// Sets an exclusive upper bound on the counters the stream
+            /// may use; `None` allows the whole counter space. A limit at or
+            /// below the counter last set is an empty range: generating a
+            /// block panics under every policy, and `try_fill_bytes` fails
+            /// under `WrapPolicy::Error`.
+            pub fn set_counter_limit(&mut self, limit: Option<$ctr>) {
+                self.ctr.set_limit(limit)
+            }
+
+            pub fn wrap_policy(&self) -> $crate::counter::WrapPolicy {
+                self.ctr.policy()
+            }
+
+            pub fn set_wrap_policy(&mut self, policy: $crate::counter::WrapPolicy) {
+                self.ctr.set_policy(policy)
+            }
+
+            /// Number of blocks left before the counter range is used up,
+            /// saturating at `u128::MAX`.
+            pub fn blocks_remaining(&self) -> u128 {
+                self.ctr.remaining()
+            }
+        }
+    }
+}
+
+/// Random access into the word stream a core produces from counter zero,
+/// consumed the way `BlockRng` and `BlockRng64` consume it: block `i` comes
+/// from counter `i`, its words are used in lane order, 64-bit words are
+/// split low half first and 32-bit words are paired low word first.
+macro_rules! impl_word_at {
+    ($t: ty, $key: ty, $w: ty, $n: expr, $gen: expr) => {
+        impl $t {
+            /// The `index`-th word of the stream with key `key`.
+            pub fn word_at(key: $key, index: u128) -> $w {
+                let ctr = $crate::counter::Counter::from_u128(index / $n);
+                $gen(ctr, key)[(index % $n) as usize]
+            }
+
+            /// The `index`-th value returned by `next_u32`.
+            pub fn u32_at(key: $key, index: u128) -> u32 {
+                match ::std::mem::size_of::<$w>() {
+                    4 => Self::word_at(key, index) as u32,
+                    _ => (Self::word_at(key, index / 2) as u64 >> (32 * (index % 2))) as u32,
+                }
+            }
+
+            /// The `index`-th value returned by `next_u64`.
+            pub fn u64_at(key: $key, index: u128) -> u64 {
+                match ::std::mem::size_of::<$w>() {
+                    4 => {
+                        let index = index.wrapping_mul(2);
+                        let ctr = $crate::counter::Counter::from_u128(index / $n);
+                        let block = $gen(ctr, key);
+                        let lane = (index % $n) as usize;
+                        block[lane] as u64 | (block[lane + 1] as u64) << 32
+                    }
+                    _ => Self::word_at(key, index) as u64,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use super::{Counter, CounterState, WrapPolicy};
+
+    #[test]
+    fn increment_carries() {
+        let mut c = [u32::MAX, 0];
+        assert!(!c.increment());
+        assert_eq!(c, [0, 1]);
+
+        let mut c = [u64::MAX, u64::MAX, u64::MAX, u64::MAX];
+        assert!(c.increment());
+        assert_eq!(c, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn widen_and_add() {
+        let c = [0x11111111u32, 0x22222222, 0x33333333, 0x44444444];
+        assert_eq!(c.to_u256(), [0x2222222211111111, 0x4444444433333333, 0, 0]);
+        assert_eq!(<[u32; 4]>::from_u256(c.to_u256()), c);
+
+        assert_eq!([u32::MAX, 0].overflowing_add(1), ([0, 1], false));
+        assert_eq!([u32::MAX, u32::MAX].overflowing_add(2), ([1, 0], true));
+        assert_eq!([u64::MAX, 0, 0, 7].overflowing_add(u128::MAX), ([u64::MAX - 1, 0, 1, 7], false));
+        assert_eq!(<[u32; 2]>::from_u128(1 << 70 | 5), [5, 0]);
+    }
+
+    #[test]
+    fn ordering_and_distance() {
+        let a = [5u32, 1];
+        let b = [3u32, 2];
+        assert_eq!(a.cmp_counter(&b), Ordering::Less);
+        assert_eq!(a.distance_to(&b), (1 << 32) - 2);
+        assert_eq!(b.distance_to(&a), 0);
+        assert_eq!([0u32, 0].distance_to_wrap(), 1 << 64);
+        assert_eq!([0u64, 0, 0, 1].distance_to_wrap(), u128::MAX);
+    }
+
+    #[test]
+    fn wrap_within_range() {
+        let mut s = CounterState::new([0u32, 0]);
+        s.set([10, 0]);
+        s.set_limit(Some([12, 0]));
+        assert_eq!(s.remaining(), 2);
+        let seen: Vec<_> = (0..5).map(|_| s.take()[0]).collect();
+        assert_eq!(seen, vec![10, 11, 10, 11, 10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "counter range is empty")]
+    fn wrap_empty_range() {
+        let mut s = CounterState::new([5u32, 0]);
+        s.set_limit(Some([3, 0]));
+        assert_eq!(s.remaining(), 0);
+        s.take();
+    }
+
+    #[test]
+    #[should_panic(expected = "counter range is empty")]
+    fn wrap_limit_at_start() {
+        let mut s = CounterState::new([0u64, 0]);
+        s.set([7, 1]);
+        s.set_limit(Some([7, 1]));
+        s.take();
+    }
+
+    #[test]
+    #[should_panic(expected = "counter range exhausted")]
+    fn panic_at_end() {
+        let mut s = CounterState::new([u32::MAX, u32::MAX]);
+        s.set_policy(WrapPolicy::Panic);
+        s.take();
+        assert_eq!(s.remaining(), 0);
+        s.take();
+    }
+}
@@ -0,0 +1,323 @@
+//! Latin hypercube and stratified jittered designs on the unit cube.
+//!
+//! Both are pure functions of a key and a sample index, so any point can be
+//! recomputed alone. A Latin hypercube of `n` points sends sample `i` to
+//! stratum `σ_d(i)` on each axis `d`, where `σ_d` is a keyed `Permutation`,
+//! and jitters it within the stratum by a uniform from `philox_4x32`.
+
+use std::error;
+use std::fmt;
+
+use super::permutation::Permutation;
+use super::philox::{philox_4x32, Array2x32};
+use super::u01::u01_f64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DesignError {
+    /// A design has no points or an axis has no strata.
+    Empty,
+    /// The strata of a jittered design number more than `u64::MAX`.
+    TooManyPoints,
+    /// A point buffer has the wrong length.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A Latin hypercube candidate is `2^31 - 1` or more.
+    CandidateTooLarge { candidate: u32 },
+    /// A sample index is not below the number of points.
+    IndexOutOfRange { index: u64, len: u64 },
+}
+
+impl fmt::Display for DesignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DesignError::Empty => write!(f, "design has no points"),
+            DesignError::TooManyPoints => write!(f, "design has more than 2^64 points"),
+            DesignError::DimensionMismatch { expected, found } =>
+                write!(f, "point has {} coordinates, design has {} dimensions", found, expected),
+            DesignError::CandidateTooLarge { candidate } =>
+                write!(f, "candidate {} is not below 2^31 - 1", candidate),
+            DesignError::IndexOutOfRange { index, len } =>
+                write!(f, "index {} out of range for design of {} points", index, len),
+        }
+    }
+}
+
+impl error::Error for DesignError {}
+
+/// Jitter tag of `Jittered`. Latin hypercube candidates use `candidate << 1`
+/// and `candidate << 1 | 1` with `candidate < 2^31 - 1`, which never reach it.
+const JITTERED_TAG: u32 = u32::MAX;
+
+/// Uniform in `[0, 1)` for coordinate `dim` of sample `index`; `tag` keeps
+/// the streams of different designs and candidates apart.
+fn jitter(key: Array2x32, index: u64, dim: usize, tag: u32) -> f64 {
+    let x = philox_4x32([index as u32, (index >> 32) as u32, dim as u32, tag], key);
+    u01_f64((x[1] as u64) << 32 | x[0] as u64)
+}
+
+fn check_len(expected: usize, out: &[f64]) -> Result<(), DesignError> {
+    if out.len() == expected {
+        Ok(())
+    } else {
+        Err(DesignError::DimensionMismatch { expected, found: out.len() })
+    }
+}
+
+fn check_index(index: u64, len: u64) -> Result<(), DesignError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(DesignError::IndexOutOfRange { index, len })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LatinHypercube {
+    key: Array2x32,
+    candidate: u32,
+    n: u64,
+    perms: Vec<Permutation>,
+}
+
+impl LatinHypercube {
+    /// Design number `candidate` of `n` points in `dims` dimensions under
+    /// `key`; candidates are independent designs, and must be below
+    /// `2^31 - 1`.
+    pub fn with_candidate(n: u64, dims: usize, key: Array2x32, candidate: u32)
+                          -> Result<Self, DesignError> {
+        if n == 0 {
+            return Err(DesignError::Empty);
+        }
+        if candidate >= (1 << 31) - 1 {
+            return Err(DesignError::CandidateTooLarge { candidate });
+        }
+        let perms = (0..dims)
+            .map(|d| {
+                let perm_key = philox_4x32([d as u32, 0, 0, candidate << 1 | 1], key)[0];
+                Permutation::philox_2x32(n, [perm_key])
+            })
+            .collect();
+        Ok(Self { key, candidate, n, perms })
+    }
+
+    pub fn new(n: u64, dims: usize, key: Array2x32) -> Result<Self, DesignError> {
+        Self::with_candidate(n, dims, key, 0)
+    }
+
+    /// The candidate among the first `candidates` with the largest minimum
+    /// distance between points. The search costs `O(candidates n^2 dims)`,
+    /// and `candidate()` of the result recreates it with `with_candidate`.
+    pub fn maximin(n: u64, dims: usize, key: Array2x32, candidates: u32)
+                   -> Result<Self, DesignError> {
+        let mut best = Self::new(n, dims, key)?;
+        let mut best_distance = best.min_distance();
+        for c in 1..candidates {
+            let design = Self::with_candidate(n, dims, key, c)?;
+            let d = design.min_distance();
+            if d > best_distance {
+                best = design;
+                best_distance = d;
+            }
+        }
+        Ok(best)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dims(&self) -> usize {
+        self.perms.len()
+    }
+
+    pub fn candidate(&self) -> u32 {
+        self.candidate
+    }
+
+    /// Coordinate `dim` of sample `index`. Panics unless `index < len()`;
+    /// `point` returns an error instead.
+    pub fn coordinate(&self, index: u64, dim: usize) -> f64 {
+        let stratum = self.perms[dim].forward(index);
+        (stratum as f64 + jitter(self.key, index, dim, self.candidate << 1)) / self.n as f64
+    }
+
+    pub fn point(&self, index: u64, out: &mut [f64]) -> Result<(), DesignError> {
+        check_len(self.dims(), out)?;
+        check_index(index, self.n)?;
+        for (d, x) in out.iter_mut().enumerate() {
+            *x = self.coordinate(index, d);
+        }
+        Ok(())
+    }
+
+    /// Smallest Euclidean distance between two points of the design.
+    pub fn min_distance(&self) -> f64 {
+        let points: Vec<Vec<f64>> = (0..self.len())
+            .map(|i| (0..self.dims()).map(|d| self.coordinate(i, d)).collect())
+            .collect();
+        let mut min = f64::INFINITY;
+        for (i, p) in points.iter().enumerate() {
+            for q in &points[i + 1..] {
+                let d: f64 = p.iter().zip(q).map(|(x, y)| (x - y) * (x - y)).sum();
+                min = min.min(d);
+            }
+        }
+        min.sqrt()
+    }
+}
+
+/// One jittered point in each cell of a grid with `strata[d]` cells along
+/// axis `d`. Sample `i` lies in the cell whose mixed-radix digits, first axis
+/// fastest, spell `i`.
+#[derive(Clone, Debug)]
+pub struct Jittered {
+    key: Array2x32,
+    strata: Vec<u64>,
+    len: u64,
+}
+
+impl Jittered {
+    pub fn new(strata: &[u64], key: Array2x32) -> Result<Self, DesignError> {
+        let mut len: u64 = 1;
+        for &s in strata {
+            if s == 0 {
+                return Err(DesignError::Empty);
+            }
+            len = len.checked_mul(s).ok_or(DesignError::TooManyPoints)?;
+        }
+        Ok(Self { key, strata: strata.to_vec(), len })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn dims(&self) -> usize {
+        self.strata.len()
+    }
+
+    pub fn point(&self, index: u64, out: &mut [f64]) -> Result<(), DesignError> {
+        check_len(self.dims(), out)?;
+        check_index(index, self.len)?;
+        let mut rest = index;
+        for (d, (x, &s)) in out.iter_mut().zip(&self.strata).enumerate() {
+            *x = ((rest % s) as f64 + jitter(self.key, index, d, JITTERED_TAG)) / s as f64;
+            rest /= s;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LatinHypercube, Jittered, DesignError};
+    use distributions::ks_test;
+
+    const KEY: [u32; 2] = [0xd0e, 0x2024];
+
+    #[test]
+    fn latin_strata() {
+        let n = 97;
+        let lhs = LatinHypercube::new(n, 5, KEY).unwrap();
+        for d in 0..5 {
+            let mut seen = vec![false; n as usize];
+            for i in 0..n {
+                let x = lhs.coordinate(i, d);
+                assert!((0.0..1.0).contains(&x));
+                let s = (x * n as f64) as usize;
+                assert!(!seen[s]);
+                seen[s] = true;
+            }
+        }
+        let mut p = [0.0; 5];
+        lhs.point(40, &mut p).unwrap();
+        assert_eq!(p[3], lhs.coordinate(40, 3));
+        assert!(lhs.point(0, &mut [0.0; 4]).is_err());
+        assert_eq!(LatinHypercube::new(0, 2, KEY).unwrap_err(), DesignError::Empty);
+        assert_eq!(lhs.point(n, &mut p).unwrap_err(), DesignError::IndexOutOfRange { index: n, len: n });
+        assert!(LatinHypercube::with_candidate(n, 2, KEY, (1 << 31) - 2).is_ok());
+        assert_eq!(LatinHypercube::with_candidate(n, 2, KEY, (1 << 31) - 1).unwrap_err(),
+                   DesignError::CandidateTooLarge { candidate: (1 << 31) - 1 });
+    }
+
+    #[test]
+    fn latin_uniform() {
+        let lhs = LatinHypercube::new(5000, 2, KEY).unwrap();
+        ks_test((0..5000).map(|i| lhs.coordinate(i, 1)).collect(), |x| x);
+        // the two axes are not aligned
+        let mean = (0..5000).map(|i| lhs.coordinate(i, 0) * lhs.coordinate(i, 1)).sum::<f64>() / 5000.0;
+        assert!((mean - 0.25).abs() < 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn maximin() {
+        let (n, dims) = (30, 3);
+        let best = LatinHypercube::maximin(n, dims, KEY, 20).unwrap();
+        let distances: Vec<f64> = (0..20)
+            .map(|c| LatinHypercube::with_candidate(n, dims, KEY, c).unwrap().min_distance())
+            .collect();
+        let max = distances.iter().cloned().fold(0.0, f64::max);
+        assert_eq!(best.min_distance(), max);
+        assert!(max > distances[0] || best.candidate() == 0);
+        let again = LatinHypercube::with_candidate(n, dims, KEY, best.candidate()).unwrap();
+        assert_eq!(again.coordinate(7, 2), best.coordinate(7, 2));
+    }
+
+    #[test]
+    fn jittered_cells() {
+        let j = Jittered::new(&[4, 5, 3], KEY).unwrap();
+        assert_eq!(j.len(), 60);
+        let mut seen = [false; 60];
+        let mut p = [0.0; 3];
+        for i in 0..60 {
+            j.point(i, &mut p).unwrap();
+            let cell = (p[0] * 4.0) as usize + 4 * ((p[1] * 5.0) as usize + 5 * (p[2] * 3.0) as usize);
+            assert_eq!(cell, i as usize);
+            seen[cell] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert_eq!(Jittered::new(&[4, 0], KEY).unwrap_err(), DesignError::Empty);
+        assert_eq!(Jittered::new(&[1 << 40, 1 << 40], KEY).unwrap_err(), DesignError::TooManyPoints);
+        assert_eq!(j.point(60, &mut p).unwrap_err(), DesignError::IndexOutOfRange { index: 60, len: 60 });
+
+        let j = Jittered::new(&[4000], KEY).unwrap();
+        ks_test((0..4000).map(|i| {
+            let mut p = [0.0];
+            j.point(i, &mut p).unwrap();
+            p[0]
+        }).collect(), |x| x);
+    }
+
+    #[test]
+    fn designs_jitter_apart() {
+        // the two designs share a key and counter layout, but not jitters
+        let lhs = LatinHypercube::new(10, 2, KEY).unwrap();
+        let j = Jittered::new(&[10, 10], KEY).unwrap();
+        let (mut a, mut b) = ([0.0; 2], [0.0; 2]);
+        for i in 0..10 {
+            lhs.point(i, &mut a).unwrap();
+            j.point(i, &mut b).unwrap();
+            for d in 0..2 {
+                assert!((10.0 * a[d]).fract() != (10.0 * b[d]).fract(), "{} {}", i, d);
+            }
+        }
+    }
+
+    #[test]
+    fn golden_values() {
+        let lhs = LatinHypercube::new(10, 2, KEY).unwrap();
+        let mut p = [0.0; 2];
+        lhs.point(3, &mut p).unwrap();
+        assert_eq!(p, [0.3203179483109112, 0.4641864670433427]);
+        let j = Jittered::new(&[2, 2], KEY).unwrap();
+        j.point(3, &mut p).unwrap();
+        assert_eq!(p, [0.894503784902761, 0.7749593404765802]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::f64::consts::PI;
+
+use rand_core::RngCore;
+
+use super::{positive, ParamError, Sample};
+use u01::{u01_f64, u01_open_f64};
+
+/// Box–Muller, keeping only the cosine branch so that every normal uses
+/// exactly two words.
+pub(crate) fn standard_normal<R: RngCore + ?Sized>(rng: &mut R) -> f64 {
+    let r = (-2.0 * u01_open_f64(rng.next_u64()).ln()).sqrt();
+    r * (2.0 * PI * u01_f64(rng.next_u64())).cos()
+}
+
+/// Normal distribution. Uses two words.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Normal {
+    pub(super) mean: f64,
+    pub(super) std_dev: f64,
+}
+
+impl Normal {
+    pub fn new(mean: f64, std_dev: f64) -> Result<Self, ParamError> {
+        if !mean.is_finite() {
+            return Err(ParamError { param: "mean", value: mean });
+        }
+        Ok(Self { mean, std_dev: positive("std_dev", std_dev)? })
+    }
+}
+
+impl Sample for Normal {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.mean + self.std_dev * standard_normal(rng)
+    }
+}
+
+/// Exponential distribution with rate `lambda`. Uses one word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exp {
+    pub(super) lambda: f64,
+}
+
+impl Exp {
+    pub fn new(lambda: f64) -> Result<Self, ParamError> {
+        Ok(Self { lambda: positive("lambda", lambda)? })
+    }
+}
+
+impl Sample for Exp {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        -u01_open_f64(rng.next_u64()).ln() / self.lambda
+    }
+}
+
+/// Gamma distribution by Marsaglia and Tsang's method.
+///
+/// Each attempt uses three words (two if the normal is rejected before the
+/// uniform is drawn); more than 95% of attempts succeed for any shape.
+/// Shapes below one sample `shape + 1` and use one more word to scale down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gamma {
+    shape: f64,
+    scale: f64,
+    d: f64,
+    c: f64,
+}
+
+impl Gamma {
+    pub fn new(shape: f64, scale: f64) -> Result<Self, ParamError> {
+        let shape = positive("shape", shape)?;
+        let scale = positive("scale", scale)?;
+        let d = if shape < 1.0 { shape + 1.0 } else { shape } - 1.0 / 3.0;
+        Ok(Self { shape, scale, d, c: 1.0 / (9.0 * d).sqrt() })
+    }
+}
+
+impl Sample for Gamma {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        let x = loop {
+            let z = standard_normal(rng);
+            let v = 1.0 + self.c * z;
+            if v <= 0.0 {
+                continue;
+            }
+            let v = v * v * v;
+            let u = u01_open_f64(rng.next_u64());
+            let z2 = z * z;
+            if u < 1.0 - 0.0331 * z2 * z2 || u.ln() < 0.5 * z2 + self.d * (1.0 - v + v.ln()) {
+                break self.d * v;
+            }
+        };
+        let x = if self.shape < 1.0 {
+            x * u01_open_f64(rng.next_u64()).powf(1.0 / self.shape)
+        } else {
+            x
+        };
+        x * self.scale
+    }
+}
+
+/// Beta distribution as `X / (X + Y)` for gammas `X` and `Y`; uses the
+/// words of `Gamma(alpha)` followed by those of `Gamma(beta)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beta {
+    x: Gamma,
+    y: Gamma,
+}
+
+impl Beta {
+    pub fn new(alpha: f64, beta: f64) -> Result<Self, ParamError> {
+        let x = Gamma::new(alpha, 1.0).map_err(|e| ParamError { param: "alpha", ..e })?;
+        let y = Gamma::new(beta, 1.0).map_err(|e| ParamError { param: "beta", ..e })?;
+        Ok(Self { x, y })
+    }
+}
+
+impl Sample for Beta {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        let x = self.x.sample(rng);
+        let y = self.y.sample(rng);
+        x / (x + y)
+    }
+}
+
+/// Chi-square distribution with `k` degrees of freedom, as
+/// `Gamma(k / 2, 2)`, with the same word use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChiSquared {
+    gamma: Gamma,
+}
+
+impl ChiSquared {
+    pub fn new(k: f64) -> Result<Self, ParamError> {
+        let k = positive("k", k)?;
+        Ok(Self { gamma: Gamma::new(0.5 * k, 2.0)? })
+    }
+}
+
+impl Sample for ChiSquared {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.gamma.sample(rng)
+    }
+}
+
+/// Student's t distribution with `nu` degrees of freedom, as
+/// `Z / sqrt(V / nu)`; uses two words for `Z`, then those of
+/// `ChiSquared(nu)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StudentT {
+    nu: f64,
+    chi: ChiSquared,
+}
+
+impl StudentT {
+    pub fn new(nu: f64) -> Result<Self, ParamError> {
+        let nu = positive("nu", nu)?;
+        Ok(Self { nu, chi: ChiSquared::new(nu)? })
+    }
+}
+
+impl Sample for StudentT {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        let z = standard_normal(rng);
+        z / (self.chi.sample(rng) / self.nu).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Normal, Exp, Gamma, Beta, ChiSquared, StudentT};
+    use super::super::{close, ks_test, normal_cdf, Sample, ParamError};
+    use super::super::special::{gamma_p, beta_i};
+    use philox::Philox4x32;
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, ThreeFry2x64Rng};
+
+    const N: usize = 5000;
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([1, 2, 3, 4, 5, 6, 7, 8]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([9; 16]))
+    }
+
+    fn draw<D: Sample<Output = f64>>(d: &D) -> Vec<f64> {
+        let mut rng = threefry();
+        (0..N).map(|_| d.sample(&mut rng)).collect()
+    }
+
+    fn t_cdf(nu: f64, t: f64) -> f64 {
+        let tail = 0.5 * beta_i(0.5 * nu, 0.5, nu / (nu + t * t));
+        if t < 0.0 { tail } else { 1.0 - tail }
+    }
+
+    #[test]
+    fn params() {
+        assert_eq!(Exp::new(0.0), Err(ParamError { param: "lambda", value: 0.0 }));
+        assert!(Normal::new(f64::NAN, 1.0).is_err());
+        assert!(Normal::new(0.0, -1.0).is_err());
+        assert!(Gamma::new(1.0, f64::INFINITY).is_err());
+        assert_eq!(Beta::new(1.0, -2.0).unwrap_err().param, "beta");
+        assert!(ChiSquared::new(0.0).is_err());
+        assert!(StudentT::new(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn ks_normal_exp() {
+        ks_test(draw(&Normal::new(1.0, 2.0).unwrap()), |x| normal_cdf((x - 1.0) / 2.0));
+        ks_test(draw(&Exp::new(2.5).unwrap()), |x| 1.0 - (-2.5 * x).exp());
+    }
+
+    #[test]
+    fn ks_gamma() {
+        for &shape in [0.2, 0.5, 1.0, 3.7, 40.0].iter() {
+            ks_test(draw(&Gamma::new(shape, 2.0).unwrap()), |x| gamma_p(shape, 0.5 * x));
+        }
+    }
+
+    #[test]
+    fn ks_beta() {
+        for &(a, b) in [(2.0, 5.0), (0.5, 0.5), (1.0, 1.0), (30.0, 4.0)].iter() {
+            ks_test(draw(&Beta::new(a, b).unwrap()), |x| beta_i(a, b, x));
+        }
+    }
+
+    #[test]
+    fn ks_chi_squared_student_t() {
+        for &k in [1.0, 3.0, 12.5].iter() {
+            ks_test(draw(&ChiSquared::new(k).unwrap()), |x| gamma_p(0.5 * k, 0.5 * x));
+        }
+        for &nu in [1.0, 1.5, 5.0, 100.0].iter() {
+            ks_test(draw(&StudentT::new(nu).unwrap()), |t| t_cdf(nu, t));
+        }
+    }
+
+    // the samplers go through ln, exp and powf, which may differ in the last
+    // bits between platforms
+    fn golden<D: Sample<Output = f64>>(d: D, expected: [f64; 4]) {
+        let mut rng = philox();
+        let v: Vec<f64> = (0..4).map(|_| d.sample(&mut rng)).collect();
+        assert!(v.iter().zip(&expected).all(|(&x, &y)| close(x, y, 1e-12)), "{:?}", v);
+    }
+
+    #[test]
+    fn golden_values() {
+        golden(Normal::new(0.0, 1.0).unwrap(),
+               [-0.8686978242659382, -0.027161661145227382, -0.14742483842902934, 0.49925421236549]);
+        golden(Exp::new(1.0).unwrap(),
+               [0.4036861401415674, 0.614091352623582, 0.002155977207702239, 0.38253625808724223]);
+        golden(Gamma::new(0.5, 1.0).unwrap(),
+               [1.1939499035809107, 0.1910930643863642, 0.35091524598830914, 0.31725857960489035]);
+        golden(Gamma::new(3.0, 1.0).unwrap(),
+               [3.7054247855679625, 3.5678526947221902, 0.5740227612534862, 5.7528206071784815]);
+        golden(Beta::new(2.0, 3.0).unwrap(),
+               [0.4132016677152422, 0.03358246262728188, 0.20236059382354218, 0.2542248763444139]);
+        golden(ChiSquared::new(4.0).unwrap(),
+               [5.024699637033455, 4.795712158024954, 0.3998145223383283, 8.561988342620275]);
+        golden(StudentT::new(3.0).unwrap(),
+               [-0.9975267345127594, 0.2910427556223622, 0.43655523891922965, -0.8056429511750325]);
+    }
+}
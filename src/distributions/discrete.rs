@@ -0,0 +1,518 @@
+use rand_core::RngCore;
+
+use super::continuous::Gamma;
+use super::special::{ln_gamma, ln_choose};
+use super::{positive, ParamError, Sample};
+use u01::{u01_f64, u01_open_f64};
+
+/// Means from which Poisson switches from inversion to PTRS.
+const POISSON_PTRS_MIN: f64 = 10.0;
+/// Values of `n * min(p, 1 - p)` from which binomial switches to BTPE.
+const BINOMIAL_BTPE_MIN: f64 = 30.0;
+
+fn probability(param: &'static str, value: f64, allow_zero: bool) -> Result<f64, ParamError> {
+    if (value > 0.0 || allow_zero && value == 0.0) && value <= 1.0 {
+        Ok(value)
+    } else {
+        Err(ParamError { param, value })
+    }
+}
+
+/// Poisson distribution.
+///
+/// Means below 10 use inversion, one word per variate. Larger means use
+/// Hörmann's PTRS (transformed rejection with squeeze), two words per
+/// attempt with an acceptance rate above 85%.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Poisson {
+    lambda: f64,
+    exp_neg_lambda: f64,
+    ptrs: Ptrs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ptrs {
+    ln_lambda: f64,
+    a: f64,
+    b: f64,
+    ln_inv_alpha: f64,
+    v_r: f64,
+}
+
+impl Poisson {
+    pub fn new(lambda: f64) -> Result<Self, ParamError> {
+        let lambda = positive("lambda", lambda)?;
+        let b = 0.931 + 2.53 * lambda.sqrt();
+        let ptrs = Ptrs {
+            ln_lambda: lambda.ln(),
+            a: -0.059 + 0.02483 * b,
+            b,
+            ln_inv_alpha: (1.1239 + 1.1328 / (b - 3.4)).ln(),
+            v_r: 0.9277 - 3.6224 / (b - 2.0),
+        };
+        Ok(Self { lambda, exp_neg_lambda: (-lambda).exp(), ptrs })
+    }
+
+    fn inversion<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        let u = u01_f64(rng.next_u64());
+        let mut k = 0;
+        let mut p = self.exp_neg_lambda;
+        let mut cdf = p;
+        // stop once the terms no longer change the sum, in case rounding
+        // leaves the total just short of `u`
+        while u >= cdf && p > cdf * f64::EPSILON {
+            k += 1;
+            p *= self.lambda / k as f64;
+            cdf += p;
+        }
+        k
+    }
+
+    fn ptrs<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        let t = &self.ptrs;
+        loop {
+            let u = u01_f64(rng.next_u64()) - 0.5;
+            let v = u01_open_f64(rng.next_u64());
+            let us = 0.5 - u.abs();
+            let k = ((2.0 * t.a / us + t.b) * u + self.lambda + 0.43).floor();
+            if us >= 0.07 && v <= t.v_r {
+                return k as u64;
+            }
+            if k < 0.0 || us < 0.013 && v > us {
+                continue;
+            }
+            let lhs = v.ln() + t.ln_inv_alpha - (t.a / (us * us) + t.b).ln();
+            if lhs <= -self.lambda + k * t.ln_lambda - ln_gamma(k + 1.0) {
+                return k as u64;
+            }
+        }
+    }
+}
+
+impl Sample for Poisson {
+    type Output = u64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        if self.lambda < POISSON_PTRS_MIN {
+            self.inversion(rng)
+        } else {
+            self.ptrs(rng)
+        }
+    }
+}
+
+/// Binomial distribution: successes in `n` trials of probability `p`.
+///
+/// Sampling works with `r = min(p, 1 - p)` and reflects the result. When
+/// `n * r` is below 30 it uses inversion, one word per variate plus one for
+/// each (rare) restart; otherwise Kachitvichyanukul and Schmeiser's BTPE,
+/// two words per attempt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binomial {
+    n: u64,
+    p: f64,
+}
+
+impl Binomial {
+    pub fn new(n: u64, p: f64) -> Result<Self, ParamError> {
+        Ok(Self { n, p: probability("p", p, true)? })
+    }
+
+    fn inversion<R: RngCore + ?Sized>(&self, rng: &mut R, r: f64) -> u64 {
+        let n = self.n as f64;
+        let q = 1.0 - r;
+        let q_n = (n * q.ln()).exp();
+        let mean = n * r;
+        let bound = n.min(mean + 10.0 * (mean * q + 1.0).sqrt()) as u64;
+        let mut x = 0;
+        let mut px = q_n;
+        let mut u = u01_f64(rng.next_u64());
+        while u > px {
+            x += 1;
+            if x > bound {
+                x = 0;
+                px = q_n;
+                u = u01_f64(rng.next_u64());
+            } else {
+                u -= px;
+                px = (n - x as f64 + 1.0) * r * px / (x as f64 * q);
+            }
+        }
+        x
+    }
+
+    fn btpe<R: RngCore + ?Sized>(&self, rng: &mut R, r: f64) -> u64 {
+        let n = self.n as f64;
+        let q = 1.0 - r;
+        let nrq = n * r * q;
+        let fm = n * r + r;
+        let m = fm.floor();
+        let p1 = (2.195 * nrq.sqrt() - 4.6 * q).floor() + 0.5;
+        let xm = m + 0.5;
+        let xl = xm - p1;
+        let xr = xm + p1;
+        let c = 0.134 + 20.5 / (15.3 + m);
+        let a = (fm - xl) / (fm - xl * r);
+        let lambda_l = a * (1.0 + 0.5 * a);
+        let a = (xr - fm) / (xr * q);
+        let lambda_r = a * (1.0 + 0.5 * a);
+        let p2 = p1 * (1.0 + 2.0 * c);
+        let p3 = p2 + c / lambda_l;
+        let p4 = p3 + c / lambda_r;
+
+        loop {
+            let u = u01_f64(rng.next_u64()) * p4;
+            let mut v = u01_open_f64(rng.next_u64());
+            // triangular centre: accepted without evaluating the density
+            if u <= p1 {
+                return (xm - p1 * v + u).floor() as u64;
+            }
+            let y = if u <= p2 {
+                // parallelograms
+                let x = xl + (u - p1) / c;
+                v = v * c + 1.0 - (m - x + 0.5).abs() / p1;
+                if v > 1.0 {
+                    continue;
+                }
+                x.floor()
+            } else if u <= p3 {
+                // left exponential tail
+                let y = (xl + v.ln() / lambda_l).floor();
+                if y < 0.0 {
+                    continue;
+                }
+                v *= (u - p2) * lambda_l;
+                y
+            } else {
+                // right exponential tail
+                let y = (xr - v.ln() / lambda_r).floor();
+                if y > n {
+                    continue;
+                }
+                v *= (u - p3) * lambda_r;
+                y
+            };
+
+            let k = (y - m).abs();
+            if k <= 20.0 || k >= 0.5 * nrq - 1.0 {
+                // explicit evaluation of f(y) / f(m) by recursion
+                let s = r / q;
+                let a = s * (n + 1.0);
+                let mut f = 1.0;
+                if m < y {
+                    let mut i = m + 1.0;
+                    while i <= y {
+                        f *= a / i - s;
+                        i += 1.0;
+                    }
+                } else if m > y {
+                    let mut i = y + 1.0;
+                    while i <= m {
+                        f /= a / i - s;
+                        i += 1.0;
+                    }
+                }
+                if v <= f {
+                    return y as u64;
+                }
+                continue;
+            }
+
+            // squeeze on ln f(y), then the final comparison with Stirling
+            // corrections
+            let rho = (k / nrq) * ((k * (k / 3.0 + 0.625) + 1.0 / 6.0) / nrq + 0.5);
+            let t = -k * k / (2.0 * nrq);
+            let lv = v.ln();
+            if lv < t - rho {
+                return y as u64;
+            }
+            if lv > t + rho {
+                continue;
+            }
+            let x1 = y + 1.0;
+            let f1 = m + 1.0;
+            let z = n + 1.0 - m;
+            let w = n - y + 1.0;
+            let bound = xm * (f1 / x1).ln()
+                + (n - m + 0.5) * (z / w).ln()
+                + (y - m) * (w * r / (x1 * q)).ln()
+                + stirling(f1) + stirling(z) + stirling(x1) + stirling(w);
+            if lv <= bound {
+                return y as u64;
+            }
+        }
+    }
+}
+
+/// Correction term of Stirling's series used by BTPE.
+fn stirling(x: f64) -> f64 {
+    let x2 = x * x;
+    (13860.0 - (462.0 - (132.0 - (99.0 - 140.0 / x2) / x2) / x2) / x2) / x / 166320.0
+}
+
+impl Sample for Binomial {
+    type Output = u64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        let r = self.p.min(1.0 - self.p);
+        if r == 0.0 {
+            return if self.p == 0.0 { 0 } else { self.n };
+        }
+        let y = if self.n as f64 * r < BINOMIAL_BTPE_MIN {
+            self.inversion(rng, r)
+        } else {
+            self.btpe(rng, r)
+        };
+        if self.p > 0.5 { self.n - y } else { y }
+    }
+}
+
+/// Geometric distribution: failures before the first success in trials of
+/// probability `p`. Uses one word, by inversion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometric {
+    ln_q: f64,
+}
+
+impl Geometric {
+    pub fn new(p: f64) -> Result<Self, ParamError> {
+        let p = probability("p", p, false)?;
+        Ok(Self { ln_q: (-p).ln_1p() })
+    }
+}
+
+impl Sample for Geometric {
+    type Output = u64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        // float to int casts saturate, so p close to zero gives u64::MAX
+        // rather than wrapping
+        (u01_open_f64(rng.next_u64()).ln() / self.ln_q).floor() as u64
+    }
+}
+
+/// Negative binomial distribution: failures before the `r`-th success in
+/// trials of probability `p`, for real `r > 0`.
+///
+/// Sampled as a Poisson whose mean is `Gamma(r, (1 - p) / p)`; uses the
+/// words of the gamma and then those of the Poisson.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NegativeBinomial {
+    gamma: Option<Gamma>,
+}
+
+impl NegativeBinomial {
+    pub fn new(r: f64, p: f64) -> Result<Self, ParamError> {
+        let r = positive("r", r)?;
+        let p = probability("p", p, false)?;
+        let gamma = if p < 1.0 { Some(Gamma::new(r, (1.0 - p) / p)?) } else { None };
+        Ok(Self { gamma })
+    }
+}
+
+impl Sample for NegativeBinomial {
+    type Output = u64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        let mean = match self.gamma {
+            Some(ref gamma) => gamma.sample(rng),
+            None => return 0,
+        };
+        match Poisson::new(mean) {
+            Ok(poisson) => poisson.sample(rng),
+            // the gamma underflowed to zero
+            Err(_) => 0,
+        }
+    }
+}
+
+/// Hypergeometric distribution: successes among `draws` items taken without
+/// replacement from `total` items of which `successes` are successes.
+///
+/// Uses one word, by inversion starting at the mode and moving outwards
+/// one step on each side in turn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hypergeometric {
+    total: u64,
+    successes: u64,
+    draws: u64,
+    min: u64,
+    max: u64,
+    mode: u64,
+    p_mode: f64,
+}
+
+impl Hypergeometric {
+    pub fn new(total: u64, successes: u64, draws: u64) -> Result<Self, ParamError> {
+        if successes > total {
+            return Err(ParamError { param: "successes", value: successes as f64 });
+        }
+        if draws > total {
+            return Err(ParamError { param: "draws", value: draws as f64 });
+        }
+        let min = (draws + successes).saturating_sub(total);
+        let max = draws.min(successes);
+        let mode = ((draws as u128 + 1) * (successes as u128 + 1) / (total as u128 + 2)) as u64;
+        let mode = mode.max(min).min(max);
+        let (n, k, d) = (total as f64, successes as f64, draws as f64);
+        let x = mode as f64;
+        let p_mode = (ln_choose(k, x) + ln_choose(n - k, d - x) - ln_choose(n, d)).exp();
+        Ok(Self { total, successes, draws, min, max, mode, p_mode })
+    }
+
+    /// `P(x + 1) / P(x)`.
+    fn ratio(&self, x: u64) -> f64 {
+        let (n, k, d, x) = (self.total as f64, self.successes as f64, self.draws as f64, x as f64);
+        (k - x) * (d - x) / ((x + 1.0) * (n - k - d + x + 1.0))
+    }
+}
+
+impl Sample for Hypergeometric {
+    type Output = u64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        let mut u = u01_f64(rng.next_u64()) - self.p_mode;
+        let (mut lo, mut hi) = (self.mode, self.mode);
+        let (mut p_lo, mut p_hi) = (self.p_mode, self.p_mode);
+        while u >= 0.0 {
+            if lo == self.min && hi == self.max {
+                // only rounding left `u` positive
+                return self.mode;
+            }
+            if lo > self.min {
+                lo -= 1;
+                p_lo /= self.ratio(lo);
+                u -= p_lo;
+                if u < 0.0 {
+                    return lo;
+                }
+            }
+            if hi < self.max {
+                p_hi *= self.ratio(hi);
+                hi += 1;
+                u -= p_hi;
+                if u < 0.0 {
+                    return hi;
+                }
+            }
+        }
+        self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Poisson, Binomial, Geometric, NegativeBinomial, Hypergeometric};
+    use super::super::{chi_square_test, Sample, ParamError};
+    use super::super::special::{ln_gamma, ln_choose};
+    use philox::Philox4x32;
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, ThreeFry2x64Rng};
+
+    const N: usize = 20000;
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([1, 2, 3, 4, 5, 6, 7, 8]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([9; 16]))
+    }
+
+    fn draw<D: Sample<Output = u64>>(d: &D) -> Vec<u64> {
+        let mut rng = threefry();
+        (0..N).map(|_| d.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn params() {
+        assert_eq!(Poisson::new(-1.0), Err(ParamError { param: "lambda", value: -1.0 }));
+        assert!(Binomial::new(10, 1.5).is_err());
+        assert!(Binomial::new(10, f64::NAN).is_err());
+        assert!(Geometric::new(0.0).is_err());
+        assert!(NegativeBinomial::new(0.0, 0.5).is_err());
+        assert!(Hypergeometric::new(10, 11, 2).is_err());
+        assert!(Hypergeometric::new(10, 5, 11).is_err());
+    }
+
+    #[test]
+    fn degenerate() {
+        let mut rng = philox();
+        assert_eq!(Binomial::new(7, 0.0).unwrap().sample(&mut rng), 0);
+        assert_eq!(Binomial::new(7, 1.0).unwrap().sample(&mut rng), 7);
+        assert_eq!(Geometric::new(1.0).unwrap().sample(&mut rng), 0);
+        assert_eq!(NegativeBinomial::new(2.0, 1.0).unwrap().sample(&mut rng), 0);
+        assert_eq!(Hypergeometric::new(10, 10, 4).unwrap().sample(&mut rng), 4);
+        assert_eq!(Hypergeometric::new(10, 3, 10).unwrap().sample(&mut rng), 3);
+        assert_eq!(Hypergeometric::new(10, 0, 4).unwrap().sample(&mut rng), 0);
+    }
+
+    #[test]
+    fn chi_square_poisson() {
+        for &lambda in [0.5f64, 3.0, 9.9, 10.0, 47.3, 1000.0].iter() {
+            let pmf = |k: u64| {
+                let k = k as f64;
+                (k * lambda.ln() - lambda - ln_gamma(k + 1.0)).exp()
+            };
+            chi_square_test(&draw(&Poisson::new(lambda).unwrap()), pmf);
+        }
+    }
+
+    #[test]
+    fn chi_square_binomial() {
+        for &(n, p) in [(20, 0.3f64), (500, 0.01), (100, 0.5), (1000, 0.97), (1_000_000, 0.2)].iter() {
+            let pmf = |k: u64| {
+                let (n, k) = (n as f64, k as f64);
+                (ln_choose(n, k) + k * p.ln() + (n - k) * (-p).ln_1p()).exp()
+            };
+            chi_square_test(&draw(&Binomial::new(n, p).unwrap()), pmf);
+        }
+    }
+
+    #[test]
+    fn chi_square_geometric_negative_binomial() {
+        for &p in [0.3f64, 0.01].iter() {
+            let pmf = |k: u64| p * (1.0 - p).powi(k as i32);
+            chi_square_test(&draw(&Geometric::new(p).unwrap()), pmf);
+        }
+        for &(r, p) in [(3.5, 0.4f64), (0.7, 0.05), (40.0, 0.9)].iter() {
+            let pmf = |k: u64| {
+                let k = k as f64;
+                (ln_gamma(k + r) - ln_gamma(k + 1.0) - ln_gamma(r)
+                 + r * p.ln() + k * (1.0 - p).ln()).exp()
+            };
+            chi_square_test(&draw(&NegativeBinomial::new(r, p).unwrap()), pmf);
+        }
+    }
+
+    #[test]
+    fn chi_square_hypergeometric() {
+        for &(n, k, d) in [(50, 20, 10), (10_000, 300, 2000), (100, 90, 95)].iter() {
+            let pmf = |x: u64| {
+                let (n, k, d, x) = (n as f64, k as f64, d as f64, x as f64);
+                if x > k || d - x > n - k {
+                    return 0.0;
+                }
+                (ln_choose(k, x) + ln_choose(n - k, d - x) - ln_choose(n, d)).exp()
+            };
+            chi_square_test(&draw(&Hypergeometric::new(n, k, d).unwrap()), pmf);
+        }
+    }
+
+    fn golden<D: Sample<Output = u64>>(d: D, expected: [u64; 6]) {
+        let mut rng = philox();
+        let v: Vec<u64> = (0..6).map(|_| d.sample(&mut rng)).collect();
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn golden_values() {
+        golden(Poisson::new(4.0).unwrap(), [5, 4, 11, 5, 6, 5]);
+        golden(Poisson::new(250.0).unwrap(), [258, 270, 242, 248, 239, 256]);
+        golden(Binomial::new(40, 0.25).unwrap(), [11, 10, 18, 11, 13, 11]);
+        golden(Binomial::new(5000, 0.7).unwrap(), [3475, 3487, 3478, 3469, 3537, 3493]);
+        golden(Geometric::new(0.2).unwrap(), [1, 2, 0, 1, 0, 1]);
+        golden(NegativeBinomial::new(2.5, 0.3).unwrap(), [9, 4, 5, 4, 13, 9]);
+        golden(Hypergeometric::new(1000, 400, 60).unwrap(), [20, 21, 35, 20, 29, 20]);
+    }
+}
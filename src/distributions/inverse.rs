@@ -0,0 +1,412 @@
+//! Variates by inversion of the CDF, using exactly one word each, so that
+//! a counter and a lane identify a variate.
+
+use std::f64::consts::PI;
+
+use rand_core::RngCore;
+
+use super::continuous::{Normal, Exp};
+use super::special::erfc;
+use super::{positive, ParamError, Sample};
+use philox::{Array2x64, Array4x32, Array4x64};
+use u01::u01_open_f64;
+
+// Coefficients of Wichura's AS241, PPND16 (about 1e-16 relative error)
+// and PPND7 (about 1e-7).
+#[allow(clippy::excessive_precision)]
+const A16: [f64; 8] = [
+    3.387_132_872_796_366_608_0, 1.331_416_678_917_843_774_5e2,
+    1.971_590_950_306_551_442_7e3, 1.373_169_376_550_946_112_5e4,
+    4.592_195_393_154_987_145_7e4, 6.726_577_092_700_870_085_3e4,
+    3.343_057_558_358_812_810_5e4, 2.509_080_928_730_122_672_7e3,
+];
+#[allow(clippy::excessive_precision)]
+const B16: [f64; 8] = [
+    1.0, 4.231_333_070_160_091_125_2e1,
+    6.871_870_074_920_579_083_0e2, 5.394_196_021_424_751_107_7e3,
+    2.121_379_430_158_659_586_7e4, 3.930_789_580_009_271_061_0e4,
+    2.872_908_573_572_194_267_4e4, 5.226_495_278_852_854_561_0e3,
+];
+#[allow(clippy::excessive_precision)]
+const C16: [f64; 8] = [
+    1.423_437_110_749_683_577_34, 4.630_337_846_156_545_295_90,
+    5.769_497_221_460_691_405_50, 3.647_848_324_763_204_605_04,
+    1.270_458_252_452_368_382_58, 2.417_807_251_774_506_117_70e-1,
+    2.272_384_498_926_918_458_33e-2, 7.745_450_142_783_414_076_40e-4,
+];
+#[allow(clippy::excessive_precision)]
+const D16: [f64; 8] = [
+    1.0, 2.053_191_626_637_758_821_87,
+    1.676_384_830_183_803_849_40, 6.897_673_349_851_000_045_50e-1,
+    1.481_039_764_274_800_745_90e-1, 1.519_866_656_361_645_719_66e-2,
+    5.475_938_084_995_344_946_00e-4, 1.050_750_071_644_416_843_24e-9,
+];
+#[allow(clippy::excessive_precision)]
+const E16: [f64; 8] = [
+    6.657_904_643_501_103_777_20, 5.463_784_911_164_114_369_90,
+    1.784_826_539_917_291_335_80, 2.965_605_718_285_048_912_30e-1,
+    2.653_218_952_657_612_309_30e-2, 1.242_660_947_388_078_438_60e-3,
+    2.711_555_568_743_487_578_15e-5, 2.010_334_399_292_288_132_65e-7,
+];
+#[allow(clippy::excessive_precision)]
+const F16: [f64; 8] = [
+    1.0, 5.998_322_065_558_879_376_90e-1,
+    1.369_298_809_227_358_053_10e-1, 1.487_536_129_085_061_485_25e-2,
+    7.868_691_311_456_132_591_00e-4, 1.846_318_317_510_054_681_80e-5,
+    1.421_511_758_316_445_888_70e-7, 2.044_263_103_389_939_785_64e-15,
+];
+
+const A7: [f32; 4] = [3.387_132_7, 50.434_272, 159.291_13, 59.109_375];
+const B7: [f32; 4] = [1.0, 17.895_17, 78.757_76, 67.187_56];
+const C7: [f32; 4] = [1.423_437_3, 2.756_815_4, 1.306_728_5, 0.170_238_21];
+const D7: [f32; 3] = [1.0, 0.737_001_6, 0.120_211_33];
+const E7: [f32; 4] = [6.657_905, 3.081_226_4, 0.428_682_94, 0.017_337_204];
+const F7: [f32; 3] = [1.0, 0.241_978_94, 0.012_258_203];
+
+macro_rules! poly {
+    ($c: expr, $x: expr) => {
+        $c.iter().rev().fold(0.0, |acc, &c| acc * $x + c)
+    }
+}
+
+/// Quantile of the standard normal distribution, by AS241 (PPND16).
+pub fn normal_quantile(p: f64) -> f64 {
+    let q = p - 0.5;
+    if q.abs() <= 0.425 {
+        let r = 0.180_625 - q * q;
+        return q * poly!(A16, r) / poly!(B16, r);
+    }
+    let r = if q < 0.0 { p } else { 1.0 - p };
+    if r <= 0.0 {
+        return if r == 0.0 { q.signum() * f64::INFINITY } else { f64::NAN };
+    }
+    let r = (-r.ln()).sqrt();
+    let x = if r <= 5.0 {
+        let r = r - 1.6;
+        poly!(C16, r) / poly!(D16, r)
+    } else {
+        let r = r - 5.0;
+        poly!(E16, r) / poly!(F16, r)
+    };
+    if q < 0.0 { -x } else { x }
+}
+
+/// Quantile of the standard normal distribution in single precision, by
+/// AS241 (PPND7).
+pub fn normal_quantile_f32(p: f32) -> f32 {
+    let q = p - 0.5;
+    if q.abs() <= 0.425 {
+        let r = 0.180_625 - q * q;
+        return q * poly!(A7, r) / poly!(B7, r);
+    }
+    let r = if q < 0.0 { p } else { 1.0 - p };
+    if r <= 0.0 {
+        return if r == 0.0 { q.signum() * f32::INFINITY } else { f32::NAN };
+    }
+    let r = (-r.ln()).sqrt();
+    let x = if r <= 5.0 {
+        let r = r - 1.6;
+        poly!(C7, r) / poly!(D7, r)
+    } else {
+        let r = r - 5.0;
+        poly!(E7, r) / poly!(F7, r)
+    };
+    if q < 0.0 { -x } else { x }
+}
+
+/// Upper tail of the standard normal, `1 - Φ(x)`.
+fn normal_upper(x: f64) -> f64 {
+    0.5 * erfc(x * ::std::f64::consts::FRAC_1_SQRT_2)
+}
+
+/// Distributions that can be sampled by inverting their CDF.
+///
+/// Each variate uses one 64-bit word, mapped to `(0, 1)` by `u01_open_f64`;
+/// a 32-bit block yields one variate per pair of lanes, low lane first.
+pub trait Quantile {
+    /// The value below which a fraction `p` of the distribution lies.
+    fn quantile(&self, p: f64) -> f64;
+
+    fn invert_word(&self, word: u64) -> f64 {
+        self.quantile(u01_open_f64(word))
+    }
+
+    fn invert_block_4x32(&self, block: Array4x32) -> [f64; 2] {
+        let word = |i: usize| block[2 * i] as u64 | (block[2 * i + 1] as u64) << 32;
+        [self.invert_word(word(0)), self.invert_word(word(1))]
+    }
+
+    fn invert_block_2x64(&self, block: Array2x64) -> [f64; 2] {
+        [self.invert_word(block[0]), self.invert_word(block[1])]
+    }
+
+    fn invert_block_4x64(&self, block: Array4x64) -> [f64; 4] {
+        [self.invert_word(block[0]), self.invert_word(block[1]),
+         self.invert_word(block[2]), self.invert_word(block[3])]
+    }
+}
+
+impl Quantile for Normal {
+    fn quantile(&self, p: f64) -> f64 {
+        self.mean + self.std_dev * normal_quantile(p)
+    }
+}
+
+impl Quantile for Exp {
+    fn quantile(&self, p: f64) -> f64 {
+        -(-p).ln_1p() / self.lambda
+    }
+}
+
+macro_rules! sample_by_inversion {
+    ($($dist: ident),*) => {$(
+        impl Sample for $dist {
+            type Output = f64;
+
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+                self.invert_word(rng.next_u64())
+            }
+        }
+    )*}
+}
+
+/// Cauchy distribution with the given location and scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cauchy {
+    location: f64,
+    scale: f64,
+}
+
+impl Cauchy {
+    pub fn new(location: f64, scale: f64) -> Result<Self, ParamError> {
+        if !location.is_finite() {
+            return Err(ParamError { param: "location", value: location });
+        }
+        Ok(Self { location, scale: positive("scale", scale)? })
+    }
+}
+
+impl Quantile for Cauchy {
+    fn quantile(&self, p: f64) -> f64 {
+        self.location + self.scale * (PI * (p - 0.5)).tan()
+    }
+}
+
+/// Logistic distribution with the given location and scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Logistic {
+    location: f64,
+    scale: f64,
+}
+
+impl Logistic {
+    pub fn new(location: f64, scale: f64) -> Result<Self, ParamError> {
+        if !location.is_finite() {
+            return Err(ParamError { param: "location", value: location });
+        }
+        Ok(Self { location, scale: positive("scale", scale)? })
+    }
+}
+
+impl Quantile for Logistic {
+    fn quantile(&self, p: f64) -> f64 {
+        self.location + self.scale * (p / (1.0 - p)).ln()
+    }
+}
+
+/// Weibull distribution with the given shape `k` and scale `lambda`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weibull {
+    inv_shape: f64,
+    scale: f64,
+}
+
+impl Weibull {
+    pub fn new(shape: f64, scale: f64) -> Result<Self, ParamError> {
+        let shape = positive("shape", shape)?;
+        Ok(Self { inv_shape: 1.0 / shape, scale: positive("scale", scale)? })
+    }
+}
+
+impl Quantile for Weibull {
+    fn quantile(&self, p: f64) -> f64 {
+        self.scale * (-(-p).ln_1p()).powf(self.inv_shape)
+    }
+}
+
+/// Normal distribution restricted to `[low, high]`, where either bound may
+/// be infinite.
+///
+/// Intervals entirely above the mean are inverted through the upper tail,
+/// so that bounds many standard deviations out keep full precision. The
+/// interval must hold a probability representable as an `f64`, which
+/// allows bounds up to about 37 standard deviations from the mean.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TruncatedNormal {
+    mean: f64,
+    std_dev: f64,
+    low: f64,
+    high: f64,
+    upper: bool,
+    c_low: f64,
+    c_high: f64,
+}
+
+impl TruncatedNormal {
+    pub fn new(mean: f64, std_dev: f64, low: f64, high: f64) -> Result<Self, ParamError> {
+        if !mean.is_finite() {
+            return Err(ParamError { param: "mean", value: mean });
+        }
+        let std_dev = positive("std_dev", std_dev)?;
+        if low.is_nan() || high.is_nan() || low >= high {
+            return Err(ParamError { param: "high", value: high });
+        }
+        let (a, b) = ((low - mean) / std_dev, (high - mean) / std_dev);
+        // tail masses on the side away from the mean
+        let upper = a > 0.0;
+        let (c_low, c_high) = if upper {
+            (normal_upper(a), normal_upper(b))
+        } else {
+            (normal_upper(-a), normal_upper(-b))
+        };
+        if c_low == c_high {
+            return Err(ParamError { param: "low", value: low });
+        }
+        Ok(Self { mean, std_dev, low, high, upper, c_low, c_high })
+    }
+}
+
+impl Quantile for TruncatedNormal {
+    fn quantile(&self, p: f64) -> f64 {
+        let c = self.c_low + p * (self.c_high - self.c_low);
+        let z = if self.upper { -normal_quantile(c) } else { normal_quantile(c) };
+        (self.mean + self.std_dev * z).max(self.low).min(self.high)
+    }
+}
+
+sample_by_inversion!(Cauchy, Logistic, Weibull, TruncatedNormal);
+
+#[cfg(test)]
+mod tests {
+    use super::{normal_quantile, normal_quantile_f32, Quantile};
+    use super::{Cauchy, Logistic, Weibull, TruncatedNormal};
+    use super::super::{close, ks_test, Normal, Exp, Sample};
+    use philox::{philox_4x32, philox_4x64, Philox4x32};
+    use rng::Philox4x32Rng;
+    use u01::u01_open_f64;
+
+    // Reference quantiles computed to 50 digits with mpmath, at the exact
+    // double nearest to each probability.
+    #[allow(clippy::excessive_precision)]
+    const NORMAL: [(f64, f64); 12] = [
+        (1e-300, -37.047_096_299_361_199),
+        (1e-20, -9.262_340_089_798_407_6),
+        (1e-10, -6.361_340_902_404_056_2),
+        (0.001, -3.090_232_306_167_813_5),
+        (0.02425, -1.972_961_051_311_884_8),
+        (0.1, -1.281_551_565_544_600_4),
+        (0.3, -0.524_400_512_708_040_82),
+        (0.5, 0.0),
+        (0.7, 0.524_400_512_708_040_66),
+        (0.975, 1.959_963_984_540_053_9),
+        (0.999999, 4.753_424_308_817_087_8),
+        (0.999999999999, 7.034_486_910_047_835_2),
+    ];
+
+    #[test]
+    fn normal_accuracy() {
+        for &(p, x) in NORMAL.iter() {
+            assert!(close(normal_quantile(p), x, 1e-14), "{} {}", p, normal_quantile(p));
+            // PPND7 against PPND16 at the probability rounded to f32
+            let pf = p as f32;
+            if pf > 0.0 && pf < 1.0 {
+                let x = normal_quantile(pf as f64);
+                let y = normal_quantile_f32(pf) as f64;
+                assert!((y - x).abs() <= 1e-6 * x.abs().max(1.0), "{} {}", p, y);
+            }
+        }
+        assert_eq!(normal_quantile(0.0), f64::NEG_INFINITY);
+        assert_eq!(normal_quantile(1.0), f64::INFINITY);
+        assert!(normal_quantile(1.5).is_nan());
+        assert_eq!(normal_quantile_f32(0.5), 0.0);
+    }
+
+    #[test]
+    fn closed_forms() {
+        let exp = Exp::new(2.0).unwrap();
+        assert!(close(exp.quantile(1e-20), 5e-21, 1e-15));
+        assert!(close(exp.quantile(0.5), 0.5 * 2f64.ln(), 1e-15));
+        let cauchy = Cauchy::new(1.0, 2.0).unwrap();
+        assert!(close(cauchy.quantile(0.75), 3.0, 1e-15));
+        let logistic = Logistic::new(0.0, 1.5).unwrap();
+        assert!(close(logistic.quantile(0.8), 1.5 * 4f64.ln(), 1e-15));
+        let weibull = Weibull::new(2.0, 3.0).unwrap();
+        assert!(close(weibull.quantile(1.0 - (-1.0f64).exp()), 3.0, 1e-15));
+        let normal = Normal::new(10.0, 2.0).unwrap();
+        assert!(close(normal.quantile(0.975), 10.0 + 2.0 * 1.959_963_984_540_054, 1e-15));
+    }
+
+    #[test]
+    #[allow(clippy::excessive_precision)]
+    fn truncated_normal_accuracy() {
+        let inf = f64::INFINITY;
+        let cases = [
+            ((0.0, 1.0, 5.0, inf, 0.5), 5.132_018_332_044_298_5),
+            ((0.0, 1.0, -1.0, 0.5, 0.3), -0.471_904_673_227_932_31),
+            ((0.0, 1.0, 8.0, 9.0, 0.9), 8.278_609_037_011_551_5),
+            ((0.0, 1.0, -inf, -6.0, 0.25), -6.221_223_198_349_943),
+            ((2.0, 3.0, -1.0, 2.5, 0.75), 1.731_193_550_201_988_4),
+            ((0.0, 1.0, 30.0, 31.0, 0.1), 30.003_507_923_244_177),
+        ];
+        for &((mean, sd, low, high, p), x) in cases.iter() {
+            let t = TruncatedNormal::new(mean, sd, low, high).unwrap();
+            assert!(close(t.quantile(p), x, 1e-12), "{:?} {}", (low, high, p), t.quantile(p));
+        }
+        let t = TruncatedNormal::new(0.0, 1.0, 1.0, 1.5).unwrap();
+        assert!(close(t.quantile(0.0), 1.0, 1e-14));
+        assert!(close(t.quantile(1.0), 1.5, 1e-14));
+        assert!(t.quantile(0.0) >= 1.0 && t.quantile(1.0) <= 1.5);
+        assert!(TruncatedNormal::new(0.0, 1.0, 2.0, 2.0).is_err());
+        assert!(TruncatedNormal::new(0.0, 1.0, 50.0, 60.0).is_err());
+        assert!(TruncatedNormal::new(0.0, 1.0, f64::NAN, 1.0).is_err());
+    }
+
+    #[test]
+    fn one_word_per_variate() {
+        let key = [7, 8];
+        let block = philox_4x32([1, 2, 3, 4], key);
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let pair = normal.invert_block_4x32(block);
+        let lo = block[0] as u64 | (block[1] as u64) << 32;
+        assert_eq!(pair[0], normal_quantile(u01_open_f64(lo)));
+
+        // a stream at the same counter gives the same variates
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([7, 0, 0, 0, 8, 0, 0, 0]));
+        rng.set_counter([1, 2, 3, 4]);
+        let cauchy = Cauchy::new(0.0, 1.0).unwrap();
+        let v = [cauchy.sample(&mut rng), cauchy.sample(&mut rng)];
+        assert_eq!(v, cauchy.invert_block_4x32(block));
+
+        let block = philox_4x64([5, 6, 7, 8], [9, 10]);
+        let w = Weibull::new(1.5, 1.0).unwrap().invert_block_4x64(block);
+        assert!(w.iter().all(|&x| x > 0.0));
+        assert_eq!(w[2], Weibull::new(1.5, 1.0).unwrap().invert_word(block[2]));
+    }
+
+    #[test]
+    fn ks_inversion() {
+        let draw = |d: &dyn Fn(u64) -> f64| -> Vec<f64> {
+            (0..2500u64).flat_map(|i| {
+                let b = philox_4x64([i, 0, 0, 0], [3, 4]);
+                vec![d(b[0]), d(b[1]), d(b[2]), d(b[3])]
+            }).collect()
+        };
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        ks_test(draw(&|w| normal.invert_word(w)), |x| 1.0 - super::normal_upper(x));
+        let logistic = Logistic::new(1.0, 2.0).unwrap();
+        ks_test(draw(&|w| logistic.invert_word(w)), |x| 1.0 / (1.0 + (-(x - 1.0) / 2.0).exp()));
+        let t = TruncatedNormal::new(0.0, 1.0, 2.0, 3.0).unwrap();
+        let (qa, qb) = (super::normal_upper(2.0), super::normal_upper(3.0));
+        ks_test(draw(&|w| t.invert_word(w)), |x| (qa - super::normal_upper(x)) / (qa - qb));
+    }
+}
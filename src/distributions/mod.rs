@@ -0,0 +1,120 @@
+//! Non-uniform distributions whose output for a given generator state is
+//! fixed by this crate.
+//!
+//! All samplers draw whole `next_u64` words, so they behave the same on
+//! every `*Rng` wrapper; the number of words each one uses is documented on
+//! its type.
+
+use std::{error, fmt};
+
+use rand_core::RngCore;
+
+mod special;
+mod continuous;
+mod discrete;
+mod inverse;
+mod multivariate;
+mod ziggurat;
+
+pub use self::continuous::{Normal, Exp, Gamma, Beta, ChiSquared, StudentT};
+pub use self::discrete::{Poisson, Binomial, Geometric, NegativeBinomial, Hypergeometric};
+pub use self::inverse::{normal_quantile, normal_quantile_f32, Quantile};
+pub use self::inverse::{Cauchy, Logistic, Weibull, TruncatedNormal};
+pub use self::multivariate::{unit_vector_3, unit_vector, rotation_quaternion, rotation_matrix};
+pub use self::multivariate::{MultivariateNormal, Dirichlet};
+pub use self::ziggurat::{ZigguratNormal, ZigguratExp};
+
+pub trait Sample {
+    type Output;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Self::Output;
+}
+
+/// A distribution parameter was out of range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamError {
+    pub param: &'static str,
+    pub value: f64,
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid value {} for parameter {}", self.value, self.param)
+    }
+}
+
+impl error::Error for ParamError {}
+
+/// Checks that `value` is finite and strictly positive.
+fn positive(param: &'static str, value: f64) -> Result<f64, ParamError> {
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err(ParamError { param, value })
+    }
+}
+
+/// Standard normal CDF, for tests outside this module.
+#[cfg(test)]
+pub(crate) fn normal_cdf(x: f64) -> f64 {
+    0.5 * special::erfc(-x * ::std::f64::consts::FRAC_1_SQRT_2)
+}
+
+/// Whether `a` is within relative tolerance `tol` of `b`, or within `tol`
+/// of it when `b` is zero.
+#[cfg(test)]
+pub(crate) fn close(a: f64, b: f64, tol: f64) -> bool {
+    (a - b).abs() <= tol * if b == 0.0 { 1.0 } else { b.abs() }
+}
+
+/// Kolmogorov–Smirnov test of `samples` against `cdf`, at the 0.1% level.
+#[cfg(test)]
+pub(crate) fn ks_test<F: Fn(f64) -> f64>(mut samples: Vec<f64>, cdf: F) {
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = samples.len() as f64;
+    let mut d: f64 = 0.0;
+    for (i, &x) in samples.iter().enumerate() {
+        let f = cdf(x);
+        d = d.max(f - i as f64 / n).max((i + 1) as f64 / n - f);
+    }
+    let critical = 1.95 / n.sqrt();
+    assert!(d < critical, "KS statistic {} exceeds {}", d, critical);
+}
+
+/// Pearson chi-square test of integer `samples` against `pmf`, at the 0.1%
+/// level. Neighbouring values are pooled until each bin expects at least
+/// five samples, and the upper tail joins the last bin.
+#[cfg(test)]
+pub(crate) fn chi_square_test<F: Fn(u64) -> f64>(samples: &[u64], pmf: F) {
+    let n = samples.len() as f64;
+    let max = *samples.iter().max().unwrap();
+    let mut counts = vec![0u64; max as usize + 1];
+    for &x in samples {
+        counts[x as usize] += 1;
+    }
+    let mut bins: Vec<(f64, f64)> = Vec::new();
+    let (mut observed, mut expected, mut total) = (0.0, 0.0, 0.0);
+    for (k, &c) in counts.iter().enumerate() {
+        let e = n * pmf(k as u64);
+        observed += c as f64;
+        expected += e;
+        total += e;
+        if expected >= 5.0 {
+            bins.push((observed, expected));
+            observed = 0.0;
+            expected = 0.0;
+        }
+    }
+    expected += (n - total).max(0.0);
+    match bins.last_mut() {
+        Some(last) if expected < 5.0 => {
+            last.0 += observed;
+            last.1 += expected;
+        }
+        _ => bins.push((observed, expected)),
+    }
+    let stat: f64 = bins.iter().map(|&(o, e)| (o - e) * (o - e) / e).sum();
+    let df = (bins.len() - 1) as f64;
+    let p = 1.0 - special::gamma_p(0.5 * df, 0.5 * stat);
+    assert!(p > 1e-3, "chi-square {} with {} degrees of freedom, p = {}", stat, df, p);
+}
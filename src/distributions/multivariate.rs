@@ -0,0 +1,295 @@
+//! Directions, rotations and vector-valued distributions.
+//!
+//! For per-particle reproducibility, draw from a `CounterRng` built from the
+//! run key and a counter holding the particle and step.
+
+use std::f64::consts::PI;
+
+use rand_core::RngCore;
+
+use super::continuous::{standard_normal, Gamma};
+use super::{positive, ParamError, Sample};
+use u01::u01_f64;
+
+/// Uniform direction in three dimensions, by Archimedes' theorem: the
+/// height is uniform on `[-1, 1)`. Uses two words.
+pub fn unit_vector_3<R: RngCore + ?Sized>(rng: &mut R) -> [f64; 3] {
+    let z = 2.0 * u01_f64(rng.next_u64()) - 1.0;
+    let phi = 2.0 * PI * u01_f64(rng.next_u64());
+    let r = (1.0 - z * z).sqrt();
+    [r * phi.cos(), r * phi.sin(), z]
+}
+
+/// Uniform direction in `out.len()` dimensions, as normalized normals.
+/// Uses two words per coordinate, and redraws in the (practically
+/// impossible) case that all of them are zero.
+pub fn unit_vector<R: RngCore + ?Sized>(rng: &mut R, out: &mut [f64]) {
+    assert!(!out.is_empty(), "unit vector needs at least one dimension");
+    loop {
+        for x in out.iter_mut() {
+            *x = standard_normal(rng);
+        }
+        let norm = out.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            for x in out.iter_mut() {
+                *x /= norm;
+            }
+            return;
+        }
+    }
+}
+
+/// Uniform random rotation as a unit quaternion `[w, x, y, z]`, by
+/// Shoemake's subgroup algorithm. Uses three words.
+pub fn rotation_quaternion<R: RngCore + ?Sized>(rng: &mut R) -> [f64; 4] {
+    let u1 = u01_f64(rng.next_u64());
+    let t2 = 2.0 * PI * u01_f64(rng.next_u64());
+    let t3 = 2.0 * PI * u01_f64(rng.next_u64());
+    let (s1, s2) = ((1.0 - u1).sqrt(), u1.sqrt());
+    [s2 * t3.cos(), s1 * t2.sin(), s1 * t2.cos(), s2 * t3.sin()]
+}
+
+/// Uniform random rotation in SO(3) as a row-major matrix, from
+/// `rotation_quaternion`. Uses three words.
+pub fn rotation_matrix<R: RngCore + ?Sized>(rng: &mut R) -> [[f64; 3]; 3] {
+    let [w, x, y, z] = rotation_quaternion(rng);
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+    ]
+}
+
+/// Multivariate normal distribution `mean + L z`, for a lower triangular
+/// Cholesky factor `L` of the covariance and standard normals `z`. Uses two
+/// words per dimension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultivariateNormal {
+    mean: Vec<f64>,
+    chol: Vec<f64>,
+}
+
+impl MultivariateNormal {
+    /// `chol` is the row-major `n x n` factor; entries above the diagonal
+    /// are ignored.
+    pub fn new(mean: Vec<f64>, chol: Vec<f64>) -> Result<Self, ParamError> {
+        let n = mean.len();
+        if chol.len() != n * n {
+            return Err(ParamError { param: "chol", value: chol.len() as f64 });
+        }
+        for i in 0..n {
+            positive("chol", chol[i * n + i])?;
+        }
+        Ok(Self { mean, chol })
+    }
+
+    /// Factors the row-major covariance matrix `cov`, which must be
+    /// symmetric positive definite.
+    pub fn from_covariance(mean: Vec<f64>, cov: &[f64]) -> Result<Self, ParamError> {
+        let n = mean.len();
+        if cov.len() != n * n {
+            return Err(ParamError { param: "cov", value: cov.len() as f64 });
+        }
+        let mut l = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..=i {
+                let s: f64 = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum();
+                if i == j {
+                    let d = cov[i * n + i] - s;
+                    l[i * n + i] = positive("cov", d)?.sqrt();
+                } else {
+                    l[i * n + j] = (cov[i * n + j] - s) / l[j * n + j];
+                }
+            }
+        }
+        Self::new(mean, l)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.mean.len()
+    }
+}
+
+impl Sample for MultivariateNormal {
+    type Output = Vec<f64>;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Vec<f64> {
+        let n = self.dim();
+        let z: Vec<f64> = (0..n).map(|_| standard_normal(rng)).collect();
+        (0..n).map(|i| {
+            let row = &self.chol[i * n..i * n + i + 1];
+            self.mean[i] + row.iter().zip(&z).map(|(l, z)| l * z).sum::<f64>()
+        }).collect()
+    }
+}
+
+/// Dirichlet distribution, as gammas normalized to sum to one; uses the
+/// words of `Gamma(alpha[0])`, then `Gamma(alpha[1])`, and so on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dirichlet {
+    gammas: Vec<Gamma>,
+}
+
+impl Dirichlet {
+    pub fn new(alpha: &[f64]) -> Result<Self, ParamError> {
+        if alpha.len() < 2 {
+            return Err(ParamError { param: "alpha", value: alpha.len() as f64 });
+        }
+        let gammas = alpha.iter().map(|&a| {
+            Gamma::new(a, 1.0).map_err(|e| ParamError { param: "alpha", ..e })
+        }).collect::<Result<_, _>>()?;
+        Ok(Self { gammas })
+    }
+}
+
+impl Sample for Dirichlet {
+    type Output = Vec<f64>;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Vec<f64> {
+        let mut x: Vec<f64> = self.gammas.iter().map(|g| g.sample(rng)).collect();
+        let sum: f64 = x.iter().sum();
+        for v in x.iter_mut() {
+            *v /= sum;
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unit_vector_3, unit_vector, rotation_quaternion, rotation_matrix};
+    use super::{MultivariateNormal, Dirichlet};
+    use super::super::{ks_test, Sample};
+    use super::super::special::beta_i;
+    use rng::CounterRng;
+    use std::f64::consts::PI;
+
+    const N: u32 = 10000;
+
+    fn particle(i: u32) -> CounterRng {
+        CounterRng::new([0x1234, 0x5678], [i, 0, 0, 0])
+    }
+
+    #[test]
+    fn unit_vectors() {
+        let v: Vec<[f64; 3]> = (0..N).map(|i| unit_vector_3(&mut particle(i))).collect();
+        for x in v.iter() {
+            assert!((x[0] * x[0] + x[1] * x[1] + x[2] * x[2] - 1.0).abs() < 1e-12);
+        }
+        // every coordinate of a uniform direction is uniform on [-1, 1]
+        for c in 0..3 {
+            ks_test(v.iter().map(|x| x[c]).collect(), |t| 0.5 * (t + 1.0));
+        }
+
+        let mut x = [0.0; 3];
+        let mut coords = vec![];
+        for i in 0..N {
+            unit_vector(&mut particle(i), &mut x);
+            assert!((x.iter().map(|x| x * x).sum::<f64>() - 1.0).abs() < 1e-12);
+            coords.push(x[1]);
+        }
+        ks_test(coords, |t| 0.5 * (t + 1.0));
+
+        // in four dimensions x^2 follows Beta(1/2, 3/2)
+        let mut x = [0.0; 4];
+        let squares = (0..N).map(|i| {
+            unit_vector(&mut particle(i), &mut x);
+            x[0] * x[0]
+        }).collect();
+        ks_test(squares, |t| beta_i(0.5, 1.5, t));
+    }
+
+    #[test]
+    #[should_panic(expected = "unit vector needs at least one dimension")]
+    fn empty_unit_vector() {
+        unit_vector(&mut particle(0), &mut []);
+    }
+
+    #[test]
+    fn rotations() {
+        let mut angles = vec![];
+        let mut mean = [[0.0; 3]; 3];
+        for i in 0..N {
+            let q = rotation_quaternion(&mut particle(i));
+            assert!((q.iter().map(|x| x * x).sum::<f64>() - 1.0).abs() < 1e-12);
+            angles.push(2.0 * q[0].abs().min(1.0).acos());
+
+            let r = rotation_matrix(&mut particle(i));
+            for a in 0..3 {
+                for b in 0..3 {
+                    let dot: f64 = (0..3).map(|k| r[a][k] * r[b][k]).sum();
+                    assert!((dot - if a == b { 1.0 } else { 0.0 }).abs() < 1e-12);
+                    mean[a][b] += r[a][b] / N as f64;
+                }
+            }
+            let det = r[0][0] * (r[1][1] * r[2][2] - r[1][2] * r[2][1])
+                - r[0][1] * (r[1][0] * r[2][2] - r[1][2] * r[2][0])
+                + r[0][2] * (r[1][0] * r[2][1] - r[1][1] * r[2][0]);
+            assert!((det - 1.0).abs() < 1e-12);
+        }
+        // rotation angles of uniform rotations have density (1 - cos t) / pi
+        ks_test(angles.clone(), |t| (t - t.sin()) / PI);
+        // E[cos t] = -1/2 and E[t] = pi/2 + 2/pi
+        let n = N as f64;
+        let mean_cos = angles.iter().map(|t| t.cos()).sum::<f64>() / n;
+        let mean_angle = angles.iter().sum::<f64>() / n;
+        assert!((mean_cos + 0.5).abs() < 0.015, "{}", mean_cos);
+        assert!((mean_angle - (PI / 2.0 + 2.0 / PI)).abs() < 0.015, "{}", mean_angle);
+        // and the average rotation matrix vanishes
+        for row in mean.iter() {
+            for &m in row.iter() {
+                assert!(m.abs() < 0.02, "{:?}", mean);
+            }
+        }
+    }
+
+    #[test]
+    fn multivariate_normal() {
+        let cov = [4.0, 1.2, -0.6,
+                   1.2, 1.0, 0.3,
+                   -0.6, 0.3, 2.25];
+        let mvn = MultivariateNormal::from_covariance(vec![1.0, -2.0, 0.5], &cov).unwrap();
+        let samples: Vec<Vec<f64>> = (0..N).map(|i| mvn.sample(&mut particle(i))).collect();
+        let n = N as f64;
+        let mean: Vec<f64> = (0..3).map(|j| samples.iter().map(|s| s[j]).sum::<f64>() / n).collect();
+        for (m, e) in mean.iter().zip(&[1.0, -2.0, 0.5]) {
+            assert!((m - e).abs() < 0.05, "{:?}", mean);
+        }
+        for a in 0..3 {
+            for b in 0..3 {
+                let c = samples.iter().map(|s| (s[a] - mean[a]) * (s[b] - mean[b])).sum::<f64>() / n;
+                assert!((c - cov[a * 3 + b]).abs() < 0.1, "{} {} {}", a, b, c);
+            }
+        }
+        assert!(MultivariateNormal::from_covariance(vec![0.0; 2], &[1.0, 2.0, 2.0, 1.0]).is_err());
+        assert!(MultivariateNormal::new(vec![0.0; 2], vec![1.0; 3]).is_err());
+    }
+
+    #[test]
+    fn dirichlet() {
+        let alpha = [0.5, 2.0, 3.5];
+        let d = Dirichlet::new(&alpha).unwrap();
+        let samples: Vec<Vec<f64>> = (0..N).map(|i| d.sample(&mut particle(i))).collect();
+        for s in samples.iter() {
+            assert!((s.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        }
+        // marginals are Beta(alpha_i, sum - alpha_i)
+        for (i, &a) in alpha.iter().enumerate() {
+            ks_test(samples.iter().map(|s| s[i]).collect(), |x| beta_i(a, 6.0 - a, x));
+        }
+        assert!(Dirichlet::new(&[1.0]).is_err());
+        assert_eq!(Dirichlet::new(&[1.0, 0.0]).unwrap_err().param, "alpha");
+    }
+
+    #[test]
+    fn per_particle() {
+        // the same particle and step always gives the same draw, whatever
+        // was drawn for other particles before
+        let a = rotation_matrix(&mut particle(7));
+        let _ = unit_vector_3(&mut particle(6));
+        assert_eq!(rotation_matrix(&mut particle(7)), a);
+        assert!(rotation_matrix(&mut particle(8)) != a);
+        let d = Dirichlet::new(&[1.0; 5]).unwrap();
+        assert_eq!(d.sample(&mut particle(3)), d.sample(&mut particle(3)));
+    }
+}
@@ -0,0 +1,194 @@
+//! Special functions used by the samplers and their tests.
+
+use std::f64::consts::PI;
+
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+    771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905,
+    -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7,
+];
+
+/// Natural log of the gamma function for `x > 0`, by the Lanczos
+/// approximation (relative error around 1e-15).
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // reflection: Γ(x) Γ(1 - x) = π / sin(πx)
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut a = LANCZOS[0];
+    let t = x + LANCZOS_G + 0.5;
+    for (i, &c) in LANCZOS.iter().enumerate().skip(1) {
+        a += c / (x + i as f64);
+    }
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+/// Natural log of the binomial coefficient `n` choose `k`.
+pub fn ln_choose(n: f64, k: f64) -> f64 {
+    ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0)
+}
+
+const EPS: f64 = 1e-15;
+const TINY: f64 = 1e-300;
+
+/// Regularized lower incomplete gamma function P(a, x).
+#[cfg(test)]
+pub fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        0.0
+    } else if x < a + 1.0 {
+        gamma_series(a, x)
+    } else {
+        1.0 - gamma_cf(a, x)
+    }
+}
+
+fn gamma_series(a: f64, x: f64) -> f64 {
+    let mut ap = a;
+    let mut sum = 1.0 / a;
+    let mut del = sum;
+    for _ in 0..1000 {
+        ap += 1.0;
+        del *= x / ap;
+        sum += del;
+        if del.abs() < sum.abs() * EPS {
+            break;
+        }
+    }
+    sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+}
+
+/// Q(a, x) by Lentz's continued fraction, for `x >= a + 1`.
+fn gamma_cf(a: f64, x: f64) -> f64 {
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / TINY;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = b + an / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    (-x + a * x.ln() - ln_gamma(a)).exp() * h
+}
+
+/// Complementary error function, with small relative error far into the
+/// upper tail.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    // erfc(x) = Q(1/2, x^2)
+    let x2 = x * x;
+    if x2 > 750.0 {
+        // below the smallest subnormal
+        return 0.0;
+    }
+    if x2 < 1.5 {
+        1.0 - gamma_series(0.5, x2)
+    } else {
+        gamma_cf(0.5, x2)
+    }
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+#[cfg(test)]
+pub fn beta_i(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b)
+                 + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // the continued fraction converges quickly only below the mean
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_cf(a, b, x) / a
+    } else {
+        1.0 - front * beta_cf(b, a, 1.0 - x) / b
+    }
+}
+
+#[cfg(test)]
+fn beta_cf(a: f64, b: f64, x: f64) -> f64 {
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..1000 {
+        let m = m as f64;
+        for &an in [m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+                    -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0))].iter() {
+            d = 1.0 + an * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ln_gamma, ln_choose, gamma_p, beta_i, erfc};
+    use super::super::close;
+
+    #[test]
+    fn ln_gamma_values() {
+        let mut fact = 1.0f64;
+        for n in 1..30 {
+            assert!(close(ln_gamma(n as f64), fact.ln(), 1e-13), "{}", n);
+            fact *= n as f64;
+        }
+        assert!(close(ln_gamma(0.5), 0.5 * std::f64::consts::PI.ln(), 1e-14));
+        assert!(close(ln_gamma(0.1), 2.252_712_651_734_206, 1e-14));
+        assert!(close(ln_gamma(1e-5), 11.512_919_692_895_826, 1e-13));
+        assert!(close(ln_choose(10.0, 3.0), 120f64.ln(), 1e-13));
+        assert!(close(ln_gamma(1000.5), 5_908.674_175_848_677, 1e-14));
+    }
+
+    #[test]
+    fn incomplete_values() {
+        assert!(close(gamma_p(1.0, 2.0), 1.0 - (-2.0f64).exp(), 1e-14));
+        assert!(close(gamma_p(3.0, 2.5), 0.456_186_884_116_670_5, 1e-13));
+        assert!(close(gamma_p(0.5, 30.0), 1.0, 1e-14));
+        assert!(close(erfc(0.5), 0.479_500_122_186_953_5, 1e-14));
+        assert!(close(erfc(-1.0), 1.842_700_792_949_715, 1e-14));
+        // the tail must be accurate relative to its size
+        assert!((erfc(3.0) / 2.209_049_699_858_544e-5 - 1.0).abs() < 1e-13);
+        assert_eq!(erfc(f64::INFINITY), 0.0);
+        assert_eq!(erfc(-f64::INFINITY), 2.0);
+        assert!((erfc(26.0) / 5.663_192_408_856_143e-296 - 1.0).abs() < 1e-12);
+        assert!(close(beta_i(2.0, 3.0, 0.4), 0.5248, 1e-13));
+        assert!(close(beta_i(0.5, 0.5, 0.25), 1.0 / 3.0, 1e-13));
+        assert!(close(beta_i(5.0, 1.0, 0.9), 0.59049, 1e-13));
+    }
+}
@@ -0,0 +1,302 @@
+//! Ziggurat samplers for the standard normal and exponential distributions.
+//!
+//! These use the 256-layer ziggurat of Marsaglia and Tsang, modified as
+//! suggested by Doornik so that the layer index comes from the low byte of a
+//! word and the uniform from its high bits, which the original shared. Each
+//! attempt uses one `next_u64` word and about 99% succeed; the wedge test
+//! and the tail use further words. The `f32` samplers draw the same words,
+//! taking the uniform from the high half.
+
+use rand_core::RngCore;
+
+use super::Sample;
+use u01::{u01_f32, u01_f64, u01_open_f32, u01_open_f64};
+
+const LAYERS: usize = 256;
+
+const NORMAL_R: f64 = 3.654_152_885_361_009;
+const NORMAL_V: f64 = 4.928_673_233_99e-3;
+const EXP_R: f64 = 7.697_117_470_131_05;
+const EXP_V: f64 = 3.949_659_822_581_557e-3;
+
+/// Layer edges `x` and densities `f(x)`; layer 0 is the base strip, whose
+/// "edge" is its area divided by `f(r)`.
+#[derive(Clone)]
+struct Tables {
+    x: [f64; LAYERS + 1],
+    f: [f64; LAYERS + 1],
+    x32: [f32; LAYERS + 1],
+    f32: [f32; LAYERS + 1],
+}
+
+impl Tables {
+    fn new<F: Fn(f64) -> f64, G: Fn(f64) -> f64>(r: f64, v: f64, pdf: F, inv_pdf: G) -> Self {
+        let mut x = [0.0; LAYERS + 1];
+        x[0] = v / pdf(r);
+        x[1] = r;
+        for i in 1..LAYERS - 1 {
+            x[i + 1] = inv_pdf(v / x[i] + pdf(x[i]));
+        }
+        let mut f = [0.0; LAYERS + 1];
+        for i in 0..LAYERS + 1 {
+            f[i] = pdf(x[i]);
+        }
+        let mut x32 = [0.0; LAYERS + 1];
+        let mut f32 = [0.0; LAYERS + 1];
+        for i in 0..LAYERS + 1 {
+            x32[i] = x[i] as f32;
+            f32[i] = f[i] as f32;
+        }
+        Self { x, f, x32, f32 }
+    }
+}
+
+/// Standard normal distribution by the ziggurat method.
+///
+/// The tables are built by `new`, so keep one sampler around rather than
+/// creating one per draw.
+#[derive(Clone)]
+pub struct ZigguratNormal {
+    t: Tables,
+}
+
+impl ZigguratNormal {
+    pub fn new() -> Self {
+        let t = Tables::new(NORMAL_R, NORMAL_V,
+                            |x| (-0.5 * x * x).exp(),
+                            |y| (-2.0 * y.ln()).sqrt());
+        Self { t }
+    }
+
+    pub fn sample_f32<R: RngCore + ?Sized>(&self, rng: &mut R) -> f32 {
+        let t = &self.t;
+        loop {
+            let bits = rng.next_u64();
+            let i = (bits & 0xff) as usize;
+            let u = 2.0 * u01_f32((bits >> 32) as u32) - 1.0;
+            let x = u * t.x32[i];
+            if x.abs() < t.x32[i + 1] {
+                return x;
+            }
+            if i == 0 {
+                let r = NORMAL_R as f32;
+                loop {
+                    let x = u01_open_f32((rng.next_u64() >> 32) as u32).ln() / r;
+                    let y = u01_open_f32((rng.next_u64() >> 32) as u32).ln();
+                    if -2.0 * y >= x * x {
+                        return if u < 0.0 { x - r } else { r - x };
+                    }
+                }
+            }
+            let w = u01_f32((rng.next_u64() >> 32) as u32);
+            if t.f32[i + 1] + (t.f32[i] - t.f32[i + 1]) * w < (-0.5 * x * x).exp() {
+                return x;
+            }
+        }
+    }
+}
+
+impl Default for ZigguratNormal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sample for ZigguratNormal {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        let t = &self.t;
+        loop {
+            let bits = rng.next_u64();
+            let i = (bits & 0xff) as usize;
+            let u = 2.0 * u01_f64(bits) - 1.0;
+            let x = u * t.x[i];
+            if x.abs() < t.x[i + 1] {
+                return x;
+            }
+            if i == 0 {
+                // Marsaglia's method for the tail beyond r
+                loop {
+                    let x = u01_open_f64(rng.next_u64()).ln() / NORMAL_R;
+                    let y = u01_open_f64(rng.next_u64()).ln();
+                    if -2.0 * y >= x * x {
+                        return if u < 0.0 { x - NORMAL_R } else { NORMAL_R - x };
+                    }
+                }
+            }
+            let w = u01_f64(rng.next_u64());
+            if t.f[i + 1] + (t.f[i] - t.f[i + 1]) * w < (-0.5 * x * x).exp() {
+                return x;
+            }
+        }
+    }
+}
+
+/// Standard exponential distribution by the ziggurat method.
+#[derive(Clone)]
+pub struct ZigguratExp {
+    t: Tables,
+}
+
+impl ZigguratExp {
+    pub fn new() -> Self {
+        Self { t: Tables::new(EXP_R, EXP_V, |x| (-x).exp(), |y| -y.ln()) }
+    }
+
+    pub fn sample_f32<R: RngCore + ?Sized>(&self, rng: &mut R) -> f32 {
+        let t = &self.t;
+        loop {
+            let bits = rng.next_u64();
+            let i = (bits & 0xff) as usize;
+            let x = u01_f32((bits >> 32) as u32) * t.x32[i];
+            if x < t.x32[i + 1] {
+                return x;
+            }
+            if i == 0 {
+                return EXP_R as f32 - u01_open_f32((rng.next_u64() >> 32) as u32).ln();
+            }
+            let w = u01_f32((rng.next_u64() >> 32) as u32);
+            if t.f32[i + 1] + (t.f32[i] - t.f32[i + 1]) * w < (-x).exp() {
+                return x;
+            }
+        }
+    }
+}
+
+impl Default for ZigguratExp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sample for ZigguratExp {
+    type Output = f64;
+
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> f64 {
+        let t = &self.t;
+        loop {
+            let bits = rng.next_u64();
+            let i = (bits & 0xff) as usize;
+            let x = u01_f64(bits) * t.x[i];
+            if x < t.x[i + 1] {
+                return x;
+            }
+            if i == 0 {
+                // the tail beyond r is r plus a standard exponential
+                return EXP_R - u01_open_f64(rng.next_u64()).ln();
+            }
+            let w = u01_f64(rng.next_u64());
+            if t.f[i + 1] + (t.f[i] - t.f[i + 1]) * w < (-x).exp() {
+                return x;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ZigguratNormal, ZigguratExp, NORMAL_R, NORMAL_V, EXP_V, LAYERS};
+    use super::super::{close, ks_test, normal_cdf, Sample};
+    use super::super::special::erfc;
+    use philox::{Philox4x32, Philox4x64};
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, Philox4x64Rng, ThreeFry2x64Rng};
+
+    const N: usize = 20000;
+
+    fn philox() -> Philox4x64Rng {
+        Philox4x64Rng::from(Philox4x64::from_seed([3; 16]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([9; 16]))
+    }
+
+    #[test]
+    fn tables() {
+        // the constants close the ziggurat: the top layer ends at the mode
+        // with the same area as every other
+        let z = ZigguratNormal::new();
+        let top = z.t.x[LAYERS - 1] * (1.0 - z.t.f[LAYERS - 1]);
+        assert!((top - NORMAL_V).abs() < 1e-9, "{}", top);
+        assert!(z.t.x[0] > z.t.x[1] && z.t.x[1] == NORMAL_R);
+        assert!(z.t.x.windows(2).skip(1).all(|w| w[0] > w[1]));
+        let e = ZigguratExp::new();
+        let top = e.t.x[LAYERS - 1] * (1.0 - e.t.f[LAYERS - 1]);
+        assert!((top - EXP_V).abs() < 1e-9, "{}", top);
+    }
+
+    #[test]
+    fn ks_normal() {
+        let z = ZigguratNormal::new();
+        let mut rng = philox();
+        ks_test((0..N).map(|_| z.sample(&mut rng)).collect(), normal_cdf);
+        let mut rng = threefry();
+        ks_test((0..N).map(|_| z.sample_f32(&mut rng) as f64).collect(), normal_cdf);
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([1; 8]));
+        ks_test((0..N).map(|_| z.sample(&mut rng)).collect(), normal_cdf);
+    }
+
+    #[test]
+    fn ks_exp() {
+        let e = ZigguratExp::new();
+        let mut rng = threefry();
+        ks_test((0..N).map(|_| e.sample(&mut rng)).collect(), |x| 1.0 - (-x).exp());
+        let mut rng = philox();
+        ks_test((0..N).map(|_| e.sample_f32(&mut rng) as f64).collect(), |x| 1.0 - (-x).exp());
+    }
+
+    #[test]
+    fn moments_and_tails() {
+        let z = ZigguratNormal::new();
+        let mut rng = threefry();
+        let n = 200_000;
+        let (mut s1, mut s2, mut s4, mut tail) = (0.0, 0.0, 0.0, 0);
+        for _ in 0..n {
+            let x = z.sample(&mut rng);
+            s1 += x;
+            s2 += x * x;
+            s4 += x * x * x * x;
+            if x.abs() > NORMAL_R {
+                tail += 1;
+            }
+        }
+        let n = n as f64;
+        assert!((s1 / n).abs() < 0.01);
+        assert!((s2 / n - 1.0).abs() < 0.01);
+        assert!((s4 / n - 3.0).abs() < 0.06);
+        // 2 * (1 - Φ(r)) * n is about 52
+        let expected = erfc(NORMAL_R * ::std::f64::consts::FRAC_1_SQRT_2) * n;
+        assert!((tail as f64 - expected).abs() < 4.0 * expected.sqrt(), "{} {}", tail, expected);
+
+        let e = ZigguratExp::new();
+        let mut rng = philox();
+        let mean = (0..100_000).map(|_| e.sample(&mut rng)).sum::<f64>() / 100_000.0;
+        assert!((mean - 1.0).abs() < 0.01);
+    }
+
+    // the tables come from exp and ln, which may differ in the last bits
+    // between platforms
+    fn golden(v: &[f64], expected: &[f64], tol: f64) {
+        assert!(v.iter().zip(expected).all(|(&x, &y)| close(x, y, tol)), "{:?}", v);
+    }
+
+    #[test]
+    fn golden_values() {
+        let z = ZigguratNormal::new();
+        let e = ZigguratExp::new();
+        let mut rng = philox();
+        let v: Vec<f64> = (0..4).map(|_| z.sample(&mut rng)).collect();
+        golden(&v, &[-0.9254628820191803, -0.9537759540004423,
+                     0.6617821757922379, 0.9154318458185692], 1e-12);
+        let v: Vec<f64> = (0..4).map(|_| z.sample_f32(&mut rng) as f64).collect();
+        golden(&v, &[1.4210469, -1.433475, -0.54813987, -1.2547315], 1e-6);
+        let mut rng = threefry();
+        let v: Vec<f64> = (0..4).map(|_| e.sample(&mut rng)).collect();
+        golden(&v, &[0.6850979081780687, 0.881702278659442,
+                     0.08536611466444331, 0.05892798914985138], 1e-12);
+        let v: Vec<f64> = (0..4).map(|_| e.sample_f32(&mut rng) as f64).collect();
+        golden(&v, &[1.4652919, 0.84461164, 0.54029095, 0.28462747], 1e-6);
+    }
+}
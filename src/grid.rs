@@ -0,0 +1,210 @@
+use std::error;
+use std::fmt;
+
+use super::counter::Counter;
+use super::philox::{philox_4x32, philox_4x64};
+use super::philox::{Array2x32, Array4x32, Array2x64, Array4x64};
+use super::u01::{u01_f32, u01_f64};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridError {
+    /// An axis was declared with a bound of zero.
+    EmptyAxis { axis: usize },
+    /// The axes together need more bits than the counter provides.
+    TooManyBits { bits: u32, available: u32 },
+    /// An index has the wrong number of coordinates.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A coordinate is not below the bound of its axis.
+    OutOfBounds { axis: usize, index: u64, bound: u64 },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GridError::EmptyAxis { axis } =>
+                write!(f, "axis {} has a bound of zero", axis),
+            GridError::TooManyBits { bits, available } =>
+                write!(f, "grid needs {} counter bits but only {} are available",
+                       bits, available),
+            GridError::DimensionMismatch { expected, found } =>
+                write!(f, "index has {} coordinates, grid has {} axes", found, expected),
+            GridError::OutOfBounds { axis, index, bound } =>
+                write!(f, "index {} on axis {} is not below bound {}", index, axis, bound),
+        }
+    }
+}
+
+impl error::Error for GridError {}
+
+/// Number of bits needed for values in `[0, bound)`.
+fn bits_for(bound: u64) -> u32 {
+    64 - (bound - 1).leading_zeros()
+}
+
+fn layout(bounds: &[u64], available: u32) -> Result<Vec<u32>, GridError> {
+    let mut shifts = Vec::with_capacity(bounds.len());
+    let mut bits = 0;
+    for (axis, &bound) in bounds.iter().enumerate() {
+        if bound == 0 {
+            return Err(GridError::EmptyAxis { axis });
+        }
+        shifts.push(bits);
+        bits += bits_for(bound);
+    }
+    if bits > available {
+        return Err(GridError::TooManyBits { bits, available });
+    }
+    Ok(shifts)
+}
+
+fn pack(bounds: &[u64], shifts: &[u32], index: &[u64]) -> Result<[u64; 4], GridError> {
+    if index.len() != bounds.len() {
+        return Err(GridError::DimensionMismatch { expected: bounds.len(), found: index.len() });
+    }
+    let mut words = [0u64; 4];
+    for (axis, (&i, &bound)) in index.iter().zip(bounds).enumerate() {
+        if i >= bound {
+            return Err(GridError::OutOfBounds { axis, index: i, bound });
+        }
+        let shift = shifts[axis];
+        // a bound-1 axis takes no bits and may sit past the end of the counter
+        if bits_for(bound) == 0 {
+            continue;
+        }
+        words[(shift / 64) as usize] |= i << (shift % 64);
+        if !shift.is_multiple_of(64) && shift % 64 + bits_for(bound) > 64 {
+            words[(shift / 64 + 1) as usize] |= i >> (64 - shift % 64);
+        }
+    }
+    Ok(words)
+}
+
+macro_rules! grid {
+    ($grid: ident, $key: ty, $ctr: ty, $bits: expr, $gen: ident) => {
+        /// Randoms indexed by a cell of an N-dimensional grid. Each axis gets
+        /// just enough counter bits for its bound, axis 0 in the least
+        /// significant bits, and each cell maps to one counter.
+        #[derive(Clone, Debug)]
+        pub struct $grid {
+            key: $key,
+            bounds: Vec<u64>,
+            shifts: Vec<u32>,
+        }
+
+        impl $grid {
+            pub fn new(key: $key, bounds: &[u64]) -> Result<Self, GridError> {
+                let shifts = layout(bounds, $bits)?;
+                Ok(Self { key, bounds: bounds.to_vec(), shifts })
+            }
+
+            pub fn bounds(&self) -> &[u64] {
+                &self.bounds
+            }
+
+            pub fn counter(&self, index: &[u64]) -> Result<$ctr, GridError> {
+                pack(&self.bounds, &self.shifts, index).map(<$ctr>::from_u256)
+            }
+
+            pub fn block(&self, index: &[u64]) -> Result<$ctr, GridError> {
+                self.counter(index).map(|ctr| $gen(ctr, self.key))
+            }
+        }
+    }
+}
+
+grid!(Grid4x32, Array2x32, Array4x32, 128, philox_4x32);
+grid!(Grid4x64, Array2x64, Array4x64, 256, philox_4x64);
+
+impl Grid4x32 {
+    /// Four floats uniform on [0, 1), one from each word of the block.
+    pub fn uniforms(&self, index: &[u64]) -> Result<[f32; 4], GridError> {
+        let b = self.block(index)?;
+        Ok([u01_f32(b[0]), u01_f32(b[1]), u01_f32(b[2]), u01_f32(b[3])])
+    }
+
+    /// Two doubles uniform on [0, 1), each from a pair of words.
+    pub fn uniforms_f64(&self, index: &[u64]) -> Result<[f64; 2], GridError> {
+        let b = self.block(index)?;
+        Ok([u01_f64(b[0] as u64 | (b[1] as u64) << 32),
+            u01_f64(b[2] as u64 | (b[3] as u64) << 32)])
+    }
+}
+
+impl Grid4x64 {
+    /// Four doubles uniform on [0, 1), one from each word of the block.
+    pub fn uniforms(&self, index: &[u64]) -> Result<[f64; 4], GridError> {
+        let b = self.block(index)?;
+        Ok([u01_f64(b[0]), u01_f64(b[1]), u01_f64(b[2]), u01_f64(b[3])])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Grid4x32, Grid4x64, GridError};
+    use philox::{philox_4x32, philox_4x64};
+
+    #[test]
+    fn bit_budget() {
+        assert!(Grid4x32::new([1, 2], &[1 << 32, 1 << 32, 1 << 32, 1 << 32]).is_ok());
+        assert_eq!(Grid4x32::new([1, 2], &[1 << 32, 1 << 32, 1 << 32, (1 << 32) + 1]).err(),
+                   Some(GridError::TooManyBits { bits: 129, available: 128 }));
+        assert!(Grid4x64::new([1, 2], &[u64::MAX, u64::MAX, u64::MAX, u64::MAX]).is_ok());
+        assert_eq!(Grid4x64::new([1, 2], &[3, 0]).err(), Some(GridError::EmptyAxis { axis: 1 }));
+        assert!(Grid4x32::new([1, 2], &[]).is_ok());
+    }
+
+    #[test]
+    fn index_checks() {
+        let grid = Grid4x32::new([1, 2], &[10, 20, 30]).unwrap();
+        assert_eq!(grid.block(&[1, 2]).err(),
+                   Some(GridError::DimensionMismatch { expected: 3, found: 2 }));
+        assert_eq!(grid.block(&[1, 20, 3]).err(),
+                   Some(GridError::OutOfBounds { axis: 1, index: 20, bound: 20 }));
+    }
+
+    #[test]
+    fn packing() {
+        let grid = Grid4x32::new([1, 2], &[10, 20, 30, 1 << 40]).unwrap();
+        // 4 + 5 + 5 bits, then 40 bits straddling the first two words.
+        let ctr = grid.counter(&[9, 19, 29, 0xab_cdef_1234]).unwrap();
+        let lo = 9 | 19 << 4 | 29 << 9 | 0xab_cdef_1234u64 << 14;
+        assert_eq!(ctr, [lo as u32, (lo >> 32) as u32, 0, 0]);
+        assert_eq!(grid.block(&[9, 19, 29, 0xab_cdef_1234]).unwrap(), philox_4x32(ctr, [1, 2]));
+
+        let grid = Grid4x64::new([3, 4], &[1 << 60, 1 << 60, 1 << 60, 1 << 60]).unwrap();
+        let ctr = grid.counter(&[1, (1 << 59) + 5, 3, 4]).unwrap();
+        assert_eq!(ctr, [1 | 5 << 60, 1 << 55 | 3 << 56, 4 << 52, 0]);
+        assert_eq!(grid.block(&[1, (1 << 59) + 5, 3, 4]).unwrap(), philox_4x64(ctr, [3, 4]));
+    }
+
+    #[test]
+    fn unit_axis_after_full_budget() {
+        let grid = Grid4x64::new([1, 2], &[u64::MAX, u64::MAX, u64::MAX, u64::MAX, 1]).unwrap();
+        assert_eq!(grid.counter(&[0; 5]).unwrap(), [0; 4]);
+        assert_eq!(grid.counter(&[1, 2, 3, 4, 0]).unwrap(), [1, 2, 3, 4]);
+        let grid = Grid4x32::new([1, 2], &[1, 1 << 32, 1, 1 << 32, 1 << 32, 1 << 32, 1]).unwrap();
+        assert_eq!(grid.counter(&[0, 5, 0, 6, 7, 8, 0]).unwrap(), [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn distinct_cells() {
+        let grid = Grid4x64::new([5, 6], &[7, 5, 3, 2]).unwrap();
+        let mut seen = HashSet::new();
+        for x in 0..7 {
+            for y in 0..5 {
+                for z in 0..3 {
+                    for t in 0..2 {
+                        let u = grid.uniforms(&[x, y, z, t]).unwrap();
+                        assert!(u.iter().all(|&u| (0.0..1.0).contains(&u)));
+                        assert!(seen.insert(grid.counter(&[x, y, z, t]).unwrap()));
+                    }
+                }
+            }
+        }
+        let grid = Grid4x32::new([5, 6], &[7, 5]).unwrap();
+        assert_eq!(grid.uniforms(&[3, 4]).unwrap(), grid.uniforms(&[3, 4]).unwrap());
+        assert!(grid.uniforms_f64(&[3, 4]).unwrap().iter().all(|&u| (0.0..1.0).contains(&u)));
+    }
+}
@@ -0,0 +1,239 @@
+//! A seeded, platform-stable `Hasher` built on `threefry_2x64`.
+//!
+//! Input is absorbed in 16-byte blocks by the Matyas–Meyer–Oseas
+//! construction: the chaining value keys `threefry_2x64`, which encrypts the
+//! block, and the block is XORed back into the result. The chaining value
+//! starts as the user key, and the final block carries the total length.
+//! Integers are written little-endian and `usize` as 64 bits, so hashes do
+//! not depend on the platform; unlike `RandomState`, they also do not change
+//! between runs. This is not a cryptographic MAC.
+
+use std::hash::{BuildHasher, Hasher};
+
+use super::threefry::{threefry_2x64, Array2x64};
+
+const BLOCK_BYTES: usize = 16;
+
+/// Marks the length block, so it cannot be mistaken for message data.
+const FINAL: u64 = 0x8000_0000_0000_0000;
+
+#[derive(Clone, Debug)]
+pub struct ThreeFryHasher {
+    chain: Array2x64,
+    buf: [u8; BLOCK_BYTES],
+    len: usize,
+    total: u64,
+}
+
+fn absorb(chain: &mut Array2x64, block: &[u8; BLOCK_BYTES]) {
+    let mut m = [0u64; 2];
+    for (w, bytes) in m.iter_mut().zip(block.chunks(8)) {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(bytes);
+        *w = u64::from_le_bytes(b);
+    }
+    let mut x = [0u64; 2];
+    threefry_2x64(m, *chain, &mut x);
+    *chain = [x[0] ^ m[0], x[1] ^ m[1]];
+}
+
+impl ThreeFryHasher {
+    pub fn new(key: Array2x64) -> Self {
+        Self { chain: key, buf: [0; BLOCK_BYTES], len: 0, total: 0 }
+    }
+}
+
+macro_rules! write_le {
+    ($($name: ident($t: ty);)+) => {$(
+        fn $name(&mut self, x: $t) {
+            self.write(&x.to_le_bytes());
+        }
+    )+}
+}
+
+impl Hasher for ThreeFryHasher {
+    fn write(&mut self, mut data: &[u8]) {
+        self.total = self.total.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let n = (BLOCK_BYTES - self.len).min(data.len());
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == BLOCK_BYTES {
+                absorb(&mut self.chain, &self.buf);
+                self.len = 0;
+            }
+        }
+    }
+
+    write_le! {
+        write_u16(u16); write_u32(u32); write_u64(u64); write_u128(u128);
+        write_i16(i16); write_i32(i32); write_i64(i64); write_i128(i128);
+    }
+
+    fn write_usize(&mut self, x: usize) {
+        self.write_u64(x as u64);
+    }
+
+    fn write_isize(&mut self, x: isize) {
+        self.write_i64(x as i64);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut chain = self.chain;
+        if self.len > 0 {
+            let mut block = [0u8; BLOCK_BYTES];
+            block[..self.len].copy_from_slice(&self.buf[..self.len]);
+            absorb(&mut chain, &block);
+        }
+        let mut x = [0u64; 2];
+        threefry_2x64([self.total, FINAL], chain, &mut x);
+        x[0]
+    }
+}
+
+/// Builds `ThreeFryHasher`s under one key, for `HashMap::with_hasher` and
+/// similar. The default key is zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreeFryBuildHasher {
+    key: Array2x64,
+}
+
+impl ThreeFryBuildHasher {
+    pub fn new(key: Array2x64) -> Self {
+        Self { key }
+    }
+}
+
+impl BuildHasher for ThreeFryBuildHasher {
+    type Hasher = ThreeFryHasher;
+
+    fn build_hasher(&self) -> ThreeFryHasher {
+        ThreeFryHasher::new(self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::hash::{BuildHasher, Hasher};
+
+    use super::{ThreeFryHasher, ThreeFryBuildHasher};
+    use distributions::chi_square_test;
+
+    const KEY: [u64; 2] = [0x1234_5678, 0x9abc_def0];
+
+    fn hash_bytes(key: [u64; 2], data: &[u8]) -> u64 {
+        let mut h = ThreeFryHasher::new(key);
+        h.write(data);
+        h.finish()
+    }
+
+    #[test]
+    fn streaming() {
+        let data: Vec<u8> = (0..100).collect();
+        let whole = hash_bytes(KEY, &data);
+        for &split in [0, 1, 15, 16, 17, 50, 99].iter() {
+            let mut h = ThreeFryHasher::new(KEY);
+            h.write(&data[..split]);
+            h.write(&data[split..]);
+            assert_eq!(h.finish(), whole);
+        }
+        // finishing does not consume the state
+        let mut h = ThreeFryHasher::new(KEY);
+        h.write(&data[..30]);
+        let early = h.finish();
+        assert_eq!(early, hash_bytes(KEY, &data[..30]));
+        h.write(&data[30..]);
+        assert_eq!(h.finish(), whole);
+    }
+
+    #[test]
+    fn lengths_and_keys() {
+        // zero padding is disambiguated by the length block
+        let hashes: Vec<u64> = (0..40).map(|n| hash_bytes(KEY, &vec![0; n])).collect();
+        for i in 0..40 {
+            for j in 0..i {
+                assert!(hashes[i] != hashes[j]);
+            }
+        }
+        assert!(hash_bytes(KEY, b"abc") != hash_bytes([KEY[0], KEY[1] + 1], b"abc"));
+    }
+
+    #[test]
+    fn little_endian_integers() {
+        let mut a = ThreeFryHasher::new(KEY);
+        a.write_u32(0x0102_0304);
+        a.write_usize(7);
+        a.write_i16(-2);
+        let mut b = ThreeFryHasher::new(KEY);
+        b.write(&[4, 3, 2, 1, 7, 0, 0, 0, 0, 0, 0, 0, 0xfe, 0xff]);
+        assert_eq!(a.finish(), b.finish());
+    }
+
+    #[test]
+    fn avalanche() {
+        // flipping any input bit flips each output bit about half the time
+        let mut flips = [[0u32; 64]; 128];
+        let trials = 200;
+        for t in 0..trials {
+            let base = [(t as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15), t as u64];
+            let mut input = [0u8; 16];
+            input[..8].copy_from_slice(&base[0].to_le_bytes());
+            input[8..].copy_from_slice(&base[1].to_le_bytes());
+            let h = hash_bytes(KEY, &input);
+            for bit in 0..128 {
+                let mut flipped = input;
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                let d = h ^ hash_bytes(KEY, &flipped);
+                for (out, f) in flips[bit].iter_mut().enumerate() {
+                    *f += (d >> out & 1) as u32;
+                }
+            }
+        }
+        let (mut total, mut worst) = (0, 0.0f64);
+        for row in flips.iter() {
+            for &f in row.iter() {
+                total += f;
+                worst = worst.max((f as f64 / trials as f64 - 0.5).abs());
+            }
+        }
+        let mean = total as f64 / (128.0 * 64.0 * trials as f64);
+        assert!((mean - 0.5).abs() < 0.005, "{}", mean);
+        // 200 trials give a standard error of 0.035 per cell
+        assert!(worst < 0.2, "{}", worst);
+    }
+
+    #[test]
+    fn bucket_uniformity() {
+        let b = ThreeFryBuildHasher::new(KEY);
+        // sequential keys, the case where weak hashes cluster
+        let low: Vec<u64> = (0..50_000u64).map(|k| b.hash_one(k) % 256).collect();
+        chi_square_test(&low, |_| 1.0 / 256.0);
+        let high: Vec<u64> = (0..50_000u64).map(|k| b.hash_one(format!("key{}", k)) >> 56).collect();
+        chi_square_test(&high, |_| 1.0 / 256.0);
+    }
+
+    #[test]
+    fn hash_map() {
+        let b = ThreeFryBuildHasher::new(KEY);
+        let mut m1 = HashMap::with_hasher(b);
+        let mut m2 = HashMap::with_hasher(b);
+        for k in 0..100 {
+            m1.insert(k, k * k);
+            m2.insert(k, k * k);
+        }
+        assert!(m1.iter().eq(m2.iter()));
+        assert_eq!(ThreeFryBuildHasher::default().hash_one("x"),
+                   ThreeFryBuildHasher::new([0, 0]).hash_one("x"));
+    }
+
+    #[test]
+    fn golden_values() {
+        assert_eq!(hash_bytes([0, 0], b""), 8168714521661719981);
+        assert_eq!(hash_bytes(KEY, b"The quick brown fox jumps over the lazy dog"),
+                   6563878373542986996);
+        assert_eq!(ThreeFryBuildHasher::new(KEY).hash_one((1u8, 2u32, [3u64])),
+                   15321908611406872634);
+    }
+}
@@ -0,0 +1,183 @@
+//! Reproducible identifiers: RFC 4122 version-4 UUIDs and random strings.
+//!
+//! Each kind comes in two forms: drawn from any generator, such as a
+//! `Philox4x32Rng`, or computed from a key and an index with the raw block
+//! functions, so identifier `i` of a fixture can be recreated alone. Indexed
+//! UUIDs are the block of `philox_4x32` at counter `[i, 0, 0]`, and indexed
+//! strings draw from a `CounterRng` at counter `[i, 1, 0]`.
+
+use std::fmt;
+
+use rand_core::RngCore;
+
+use super::ints::below_u64;
+use super::philox::{philox_4x32, Array2x32};
+use super::rng::CounterRng;
+
+pub const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+pub const HEX_LOWER: &str = "0123456789abcdef";
+
+/// A UUID, shown in the hyphenated lowercase form of RFC 4122.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid([u8; 16]);
+
+impl Uuid {
+    /// Random bytes made into a version-4 UUID by fixing the version nibble
+    /// and the variant bits, leaving 122 random bits.
+    fn v4_from_bytes(mut bytes: [u8; 16]) -> Self {
+        bytes[6] = bytes[6] & 0x0f | 0x40;
+        bytes[8] = bytes[8] & 0x3f | 0x80;
+        Uuid(bytes)
+    }
+
+    /// A version-4 UUID from two `next_u64` words, low word first and each
+    /// little-endian.
+    pub fn new_v4<R: RngCore + ?Sized>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&rng.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&rng.next_u64().to_le_bytes());
+        Self::v4_from_bytes(bytes)
+    }
+
+    /// Version-4 UUID number `index` under `key`.
+    pub fn v4_at(key: Array2x32, index: u64) -> Self {
+        let block = philox_4x32([index as u32, (index >> 32) as u32, 0, 0], key);
+        let mut bytes = [0u8; 16];
+        for (chunk, w) in bytes.chunks_mut(4).zip(block.iter()) {
+            chunk.copy_from_slice(&w.to_le_bytes());
+        }
+        Self::v4_from_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Uuid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn version(&self) -> u8 {
+        self.0[6] >> 4
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// `len` characters chosen uniformly and independently from `alphabet`,
+/// each by one unbiased bounded draw.
+pub fn string<R: RngCore + ?Sized>(rng: &mut R, alphabet: &str, len: usize) -> String {
+    let chars: Vec<char> = alphabet.chars().collect();
+    assert!(!chars.is_empty(), "alphabet is empty");
+    (0..len).map(|_| chars[below_u64(rng, chars.len() as u64) as usize]).collect()
+}
+
+/// String number `index` under `key`, as `string` would draw it.
+pub fn string_at(key: Array2x32, index: u64, alphabet: &str, len: usize) -> String {
+    let mut rng = CounterRng::new(key, [index as u32, (index >> 32) as u32, 1, 0]);
+    string(&mut rng, alphabet, len)
+}
+
+pub fn alphanumeric<R: RngCore + ?Sized>(rng: &mut R, len: usize) -> String {
+    string(rng, ALPHANUMERIC, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Uuid, string, string_at, alphanumeric, ALPHANUMERIC, HEX_LOWER};
+    use distributions::chi_square_test;
+    use philox::Philox4x32;
+    use rng::Philox4x32Rng;
+
+    const KEY: [u32; 2] = [0x1d5, 0xf1e7];
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([2, 0, 0, 0, 9, 0, 0, 0]))
+    }
+
+    fn assert_v4(u: &Uuid) {
+        let s = u.to_string();
+        assert_eq!(s.len(), 36);
+        for (i, c) in s.chars().enumerate() {
+            match i {
+                8 | 13 | 18 | 23 => assert_eq!(c, '-'),
+                14 => assert_eq!(c, '4'),
+                19 => assert!("89ab".contains(c), "{}", s),
+                _ => assert!(HEX_LOWER.contains(c), "{}", s),
+            }
+        }
+        assert_eq!(u.version(), 4);
+    }
+
+    #[test]
+    fn uuid_format() {
+        let mut rng = philox();
+        for i in 0..200 {
+            assert_v4(&Uuid::new_v4(&mut rng));
+            assert_v4(&Uuid::v4_at(KEY, i));
+        }
+        assert_eq!(Uuid::from_bytes([0xab; 16]).to_string(), "abababab-abab-abab-abab-abababababab");
+        assert_eq!(Uuid::v4_at(KEY, 7), Uuid::v4_at(KEY, 7));
+        assert!(Uuid::v4_at(KEY, 7) != Uuid::v4_at(KEY, 8));
+    }
+
+    #[test]
+    fn uuid_random_bits() {
+        // every bit outside the version and variant is set half the time
+        let n = 4000;
+        let mut ones = [0u32; 128];
+        for i in 0..n {
+            let u = Uuid::v4_at(KEY, i);
+            for (bit, c) in ones.iter_mut().enumerate() {
+                *c += (u.as_bytes()[bit / 8] >> (bit % 8) & 1) as u32;
+            }
+        }
+        for (bit, &c) in ones.iter().enumerate() {
+            let fixed = bit / 8 == 6 && bit % 8 >= 4 || bit / 8 == 8 && bit % 8 >= 6;
+            if !fixed {
+                assert!((c as f64 / n as f64 - 0.5).abs() < 0.04, "bit {}: {}", bit, c);
+            }
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let mut rng = philox();
+        let s = alphanumeric(&mut rng, 5000);
+        assert_eq!(s.len(), 5000);
+        let counts: Vec<u64> = s.chars().map(|c| ALPHANUMERIC.find(c).unwrap() as u64).collect();
+        chi_square_test(&counts, |_| 1.0 / 62.0);
+        // characters outside ASCII count as one choice each
+        let t = string(&mut rng, "αβγ", 10);
+        assert_eq!(t.chars().count(), 10);
+        assert!(t.chars().all(|c| "αβγ".contains(c)));
+        assert_eq!(string(&mut rng, "x", 3), "xxx");
+        assert_eq!(string_at(KEY, 3, HEX_LOWER, 12), string_at(KEY, 3, HEX_LOWER, 12));
+        assert!(string_at(KEY, 3, HEX_LOWER, 12) != string_at(KEY, 4, HEX_LOWER, 12));
+        // a longer string extends a shorter one
+        assert!(string_at(KEY, 3, HEX_LOWER, 40).starts_with(&string_at(KEY, 3, HEX_LOWER, 12)));
+    }
+
+    #[test]
+    #[should_panic(expected = "alphabet is empty")]
+    fn empty_alphabet() {
+        string(&mut philox(), "", 1);
+    }
+
+    #[test]
+    fn golden_values() {
+        assert_eq!(Uuid::v4_at(KEY, 0).to_string(), "ac2b08ed-dd30-4d2d-a9c0-b26c23f18083");
+        assert_eq!(Uuid::new_v4(&mut philox()).to_string(), "572c6135-68fb-4a14-bcc9-f2d8e77690a6");
+        assert_eq!(string_at(KEY, 1, ALPHANUMERIC, 16), "bNH12b9L4aA2Btc4");
+    }
+}
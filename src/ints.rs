@@ -0,0 +1,291 @@
+//! Unbiased bounded integers and shuffles with a fixed algorithm, so that
+//! results for a given generator state never change between releases.
+//!
+//! Bounded values use Lemire's nearly-divisionless method: one word per
+//! draw, plus one more for each rejection (probability below
+//! `bound / 2^bits`). `u32` draws use `next_u32`, `u64` draws `next_u64`,
+//! and `u128` draws two `next_u64`, low word first. The block generators'
+//! own `below_u128` instead takes block-aligned words from their
+//! `next_u128`, so the two disagree when a generator has words buffered
+//! short of a 128-bit boundary.
+
+use rand_core::RngCore;
+
+use super::philox::{Array2x32, Array4x32};
+use super::rng::CounterRng;
+
+pub fn below_u32<R: RngCore + ?Sized>(rng: &mut R, bound: u32) -> u32 {
+    assert!(bound > 0, "bound must be positive");
+    let mut m = rng.next_u32() as u64 * bound as u64;
+    if (m as u32) < bound {
+        let threshold = bound.wrapping_neg() % bound;
+        while (m as u32) < threshold {
+            m = rng.next_u32() as u64 * bound as u64;
+        }
+    }
+    (m >> 32) as u32
+}
+
+pub fn below_u64<R: RngCore + ?Sized>(rng: &mut R, bound: u64) -> u64 {
+    assert!(bound > 0, "bound must be positive");
+    let mut m = rng.next_u64() as u128 * bound as u128;
+    if (m as u64) < bound {
+        let threshold = bound.wrapping_neg() % bound;
+        while (m as u64) < threshold {
+            m = rng.next_u64() as u128 * bound as u128;
+        }
+    }
+    (m >> 64) as u64
+}
+
+pub fn below_u128<R: RngCore + ?Sized>(rng: &mut R, bound: u128) -> u128 {
+    lemire_u128(|| next_u128(rng), bound)
+}
+
+/// `below_u128` over any source of 128-bit words.
+pub(crate) fn lemire_u128<F: FnMut() -> u128>(mut next: F, bound: u128) -> u128 {
+    assert!(bound > 0, "bound must be positive");
+    let (mut hi, mut lo) = mul_u128(next(), bound);
+    if lo < bound {
+        let threshold = bound.wrapping_neg() % bound;
+        while lo < threshold {
+            let m = mul_u128(next(), bound);
+            hi = m.0;
+            lo = m.1;
+        }
+    }
+    hi
+}
+
+/// Uniform in `[low, high)`.
+pub fn range_u32<R: RngCore + ?Sized>(rng: &mut R, low: u32, high: u32) -> u32 {
+    assert!(low < high, "empty range {}..{}", low, high);
+    low + below_u32(rng, high - low)
+}
+
+pub fn range_u64<R: RngCore + ?Sized>(rng: &mut R, low: u64, high: u64) -> u64 {
+    assert!(low < high, "empty range {}..{}", low, high);
+    low + below_u64(rng, high - low)
+}
+
+pub fn range_u128<R: RngCore + ?Sized>(rng: &mut R, low: u128, high: u128) -> u128 {
+    assert!(low < high, "empty range {}..{}", low, high);
+    low + below_u128(rng, high - low)
+}
+
+/// Fisher–Yates shuffle, swapping each position from the last down to 1
+/// with one chosen by `below_u64(i + 1)`.
+pub fn shuffle<R: RngCore + ?Sized, T>(rng: &mut R, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = below_u64(rng, i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+}
+
+fn next_u128<R: RngCore + ?Sized>(rng: &mut R) -> u128 {
+    let lo = rng.next_u64() as u128;
+    let hi = rng.next_u64() as u128;
+    hi << 64 | lo
+}
+
+/// Full 256-bit product of `a` and `b` as `(high, low)` halves.
+fn mul_u128(a: u128, b: u128) -> (u128, u128) {
+    const M: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & M);
+    let (b_hi, b_lo) = (b >> 64, b & M);
+    let ll = a_lo * b_lo;
+    let lh = a_lo * b_hi;
+    let hl = a_hi * b_lo;
+    let hh = a_hi * b_hi;
+    let mid = (ll >> 64) + (lh & M) + (hl & M);
+    let lo = (ll & M) | mid << 64;
+    let hi = hh + (lh >> 64) + (hl >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// `below_u32` as a pure function of a Philox-4x32 key and counter, drawing
+/// from `CounterRng`.
+pub fn below_u32_at(key: Array2x32, ctr: Array4x32, bound: u32) -> u32 {
+    below_u32(&mut CounterRng::new(key, ctr), bound)
+}
+
+/// `below_u64` as a pure function of a Philox-4x32 key and counter; each
+/// 64-bit word is a pair of lanes, low lane first.
+pub fn below_u64_at(key: Array2x32, ctr: Array4x32, bound: u64) -> u64 {
+    below_u64(&mut CounterRng::new(key, ctr), bound)
+}
+
+/// `below_u128` as a pure function of a Philox-4x32 key and counter; each
+/// 128-bit word is a whole block.
+pub fn below_u128_at(key: Array2x32, ctr: Array4x32, bound: u128) -> u128 {
+    below_u128(&mut CounterRng::new(key, ctr), bound)
+}
+
+/// `range_u32` as a pure function of a Philox-4x32 key and counter.
+pub fn range_u32_at(key: Array2x32, ctr: Array4x32, low: u32, high: u32) -> u32 {
+    range_u32(&mut CounterRng::new(key, ctr), low, high)
+}
+
+pub fn range_u64_at(key: Array2x32, ctr: Array4x32, low: u64, high: u64) -> u64 {
+    range_u64(&mut CounterRng::new(key, ctr), low, high)
+}
+
+pub fn range_u128_at(key: Array2x32, ctr: Array4x32, low: u128, high: u128) -> u128 {
+    range_u128(&mut CounterRng::new(key, ctr), low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{below_u32, below_u64, below_u128, range_u32, range_u64, range_u128, shuffle};
+    use super::{below_u32_at, below_u64_at, below_u128_at, mul_u128};
+    use super::{range_u32_at, range_u64_at, range_u128_at};
+    use philox::{philox_4x32, Philox4x32};
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, ThreeFry2x64Rng};
+    use distributions::chi_square_test;
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([1, 2, 3, 4, 5, 6, 7, 8]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([9; 16]))
+    }
+
+    #[test]
+    fn wide_multiply() {
+        assert_eq!(mul_u128(u128::MAX, u128::MAX), (u128::MAX - 1, 1));
+        assert_eq!(mul_u128(1 << 64, 1 << 64), (1, 0));
+        assert_eq!(mul_u128(12345, 67890), (0, 12345 * 67890));
+        let a = 0x1234_5678_9abc_def0_0fed_cba9_8765_4321u128;
+        let (hi, lo) = mul_u128(a, 1 << 100);
+        assert_eq!(lo, a << 100);
+        assert_eq!(hi, a >> 28);
+    }
+
+    #[test]
+    fn in_range() {
+        let mut rng = philox();
+        for bound in 1..300u32 {
+            assert!(below_u32(&mut rng, bound) < bound);
+            assert!(below_u64(&mut rng, bound as u64 * 3) < bound as u64 * 3);
+            assert!(below_u128(&mut rng, bound as u128) < bound as u128);
+        }
+        for _ in 0..100 {
+            assert!(below_u128(&mut rng, (1 << 127) + 1) <= 1 << 127);
+            let x = range_u64(&mut rng, 10, 13);
+            assert!((10..13).contains(&x));
+        }
+        assert_eq!(range_u32(&mut rng, 7, 8), 7);
+        assert_eq!(below_u32(&mut rng, 1), 0);
+    }
+
+    #[test]
+    fn unbiased() {
+        // with a plain modulo, values below 2^62 would come up half the time
+        // instead of a third
+        let mut rng = threefry();
+        let bound = (1u64 << 63) + (1 << 62);
+        let trials = 30000;
+        let low = (0..trials).filter(|_| below_u64(&mut rng, bound) < 1 << 62).count();
+        let p = low as f64 / trials as f64;
+        assert!((p - 1.0 / 3.0).abs() < 0.015, "{}", p);
+    }
+
+    #[test]
+    fn shuffle_is_permutation() {
+        let mut rng = philox();
+        let mut v: Vec<u32> = (0..100).collect();
+        shuffle(&mut rng, &mut v);
+        let mut sorted = v.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        assert!(v != sorted);
+        let mut empty: [u8; 0] = [];
+        shuffle(&mut rng, &mut empty);
+    }
+
+    #[test]
+    fn shuffle_uniform() {
+        // all 6 orderings of 3 items should be equally likely
+        let mut rng = threefry();
+        let mut counts = [0u32; 6];
+        let trials = 24000;
+        for _ in 0..trials {
+            let mut v = [0, 1, 2];
+            shuffle(&mut rng, &mut v);
+            let i = v[0] * 2 + (v[1] > v[2]) as usize;
+            counts[i] += 1;
+        }
+        for &c in counts.iter() {
+            assert!((c as f64 / trials as f64 - 1.0 / 6.0).abs() < 0.012, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn golden() {
+        let mut rng = philox();
+        let v: Vec<u32> = (0..6).map(|_| below_u32(&mut rng, 1000)).collect();
+        assert_eq!(v, [645, 667, 84, 541, 246, 997]);
+        let mut rng = threefry();
+        let v: Vec<u64> = (0..4).map(|_| range_u64(&mut rng, 1 << 40, 1 << 50)).collect();
+        assert_eq!(v, [745370490792191, 1022156981250068, 25257840252498, 100729161730237]);
+        let mut rng = threefry();
+        let v = range_u128(&mut rng, 1, 1 << 100);
+        assert_eq!(v, 1150732271912994431701120315341);
+        let mut rng = philox();
+        let mut v: Vec<u32> = (0..10).collect();
+        shuffle(&mut rng, &mut v);
+        assert_eq!(v, [1, 0, 2, 9, 3, 5, 8, 7, 4, 6]);
+    }
+
+    #[test]
+    fn counter_based() {
+        let key = [3, 4];
+        let ctr = [10, 0, 0, 7];
+        let block = philox_4x32(ctr, key);
+        assert_eq!(below_u32_at(key, ctr, 1 << 31), block[0] >> 1);
+        assert_eq!(below_u64_at(key, ctr, 1 << 63), (block[0] as u64 | (block[1] as u64) << 32) >> 1);
+        let whole = block[0] as u128 | (block[1] as u128) << 32
+            | (block[2] as u128) << 64 | (block[3] as u128) << 96;
+        assert_eq!(below_u128_at(key, ctr, 1 << 127), whole >> 1);
+
+        // matches a stream positioned at the same counter while the block lasts
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([3, 0, 0, 0, 4, 0, 0, 0]));
+        rng.set_counter(ctr);
+        assert_eq!(below_u32_at(key, ctr, 1000), below_u32(&mut rng, 1000));
+
+        // bounds that reject almost every word still terminate, deterministically
+        let bound = (1 << 31) + 1;
+        let x = below_u32_at(key, ctr, bound);
+        assert!(x < bound);
+        assert_eq!(x, below_u32_at(key, ctr, bound));
+        assert!(below_u32_at(key, [11, 0, 0, 7], 10) < 10);
+    }
+
+    #[test]
+    fn counter_based_ranges() {
+        let key = [3, 4];
+        let ctr = [10, 0, 9, 0];
+        let block = philox_4x32(ctr, key);
+        assert_eq!(range_u32_at(key, ctr, 10, 18), 10 + (block[0] >> 29));
+        let pair = block[0] as u64 | (block[1] as u64) << 32;
+        assert_eq!(range_u64_at(key, ctr, 1 << 40, 3 << 40), (1 << 40) + (pair >> 23));
+        let whole = block[0] as u128 | (block[1] as u128) << 32
+            | (block[2] as u128) << 64 | (block[3] as u128) << 96;
+        assert_eq!(range_u128_at(key, ctr, 5, (1 << 100) + 5), 5 + (whole >> 28));
+
+        // uniform across counters
+        let samples: Vec<u64> = (0..7000)
+            .map(|i| range_u32_at(key, [i, 0, 9, 0], 10, 17) as u64 - 10)
+            .collect();
+        chi_square_test(&samples, |k| if k < 7 { 1.0 / 7.0 } else { 0.0 });
+    }
+
+    #[test]
+    fn golden_counter_based() {
+        let key = [0xdead, 0xbeef];
+        let v: Vec<u32> = (0..6).map(|i| below_u32_at(key, [i, 0, 0, 0], 6)).collect();
+        assert_eq!(v, [4, 1, 4, 3, 2, 2]);
+    }
+}
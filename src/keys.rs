@@ -0,0 +1,223 @@
+use std::error;
+use std::fmt;
+
+use super::philox::Philox4x32;
+use super::threefry::ThreeFry2x64;
+use super::rng::{Philox4x32Rng, ThreeFry2x64Rng};
+
+const FIELD_BITS_MAX: u32 = 64;
+const KEY_BITS_MAX: u32 = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyField {
+    Run,
+    Rank,
+    Thread,
+    Stream,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyError {
+    /// A single field is wider than 64 bits.
+    FieldTooWide { field: KeyField, bits: u32 },
+    /// The fields together need more bits than the target key provides.
+    LayoutTooWide { bits: u32, available: u32 },
+    /// A field value does not fit in the bits assigned to it.
+    FieldOverflow { field: KeyField, value: u64, bits: u32 },
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyError::FieldTooWide { field, bits } =>
+                write!(f, "{:?} field is {} bits wide, at most {} are allowed",
+                       field, bits, FIELD_BITS_MAX),
+            KeyError::LayoutTooWide { bits, available } =>
+                write!(f, "key layout needs {} bits but only {} are available",
+                       bits, available),
+            KeyError::FieldOverflow { field, value, bits } =>
+                write!(f, "{:?} value {} does not fit in {} bits",
+                       field, value, bits),
+        }
+    }
+}
+
+impl error::Error for KeyError {}
+
+/// Identifies one generator within a distributed run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyId {
+    pub run: u64,
+    pub rank: u64,
+    pub thread: u64,
+    pub stream: u64,
+}
+
+/// Packs run, rank, thread and stream ids into disjoint bit fields of a
+/// generator key, so that every distinct `KeyId` gets a distinct key.
+///
+/// From the least significant bit upwards the key holds the stream,
+/// thread, rank and run fields; bits above the layout are zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyAllocator {
+    run_bits: u32,
+    rank_bits: u32,
+    thread_bits: u32,
+    stream_bits: u32,
+}
+
+impl KeyAllocator {
+    pub fn new(run_bits: u32, rank_bits: u32, thread_bits: u32, stream_bits: u32)
+        -> Result<Self, KeyError>
+    {
+        let fields = [
+            (KeyField::Run, run_bits),
+            (KeyField::Rank, rank_bits),
+            (KeyField::Thread, thread_bits),
+            (KeyField::Stream, stream_bits),
+        ];
+        for &(field, bits) in fields.iter() {
+            if bits > FIELD_BITS_MAX {
+                return Err(KeyError::FieldTooWide { field, bits });
+            }
+        }
+        let layout = Self { run_bits, rank_bits, thread_bits, stream_bits };
+        if layout.bits() > KEY_BITS_MAX {
+            return Err(KeyError::LayoutTooWide { bits: layout.bits(), available: KEY_BITS_MAX });
+        }
+        Ok(layout)
+    }
+
+    /// Total number of key bits used by the layout.
+    pub fn bits(&self) -> u32 {
+        self.run_bits + self.rank_bits + self.thread_bits + self.stream_bits
+    }
+
+    pub fn pack(&self, id: KeyId) -> Result<u128, KeyError> {
+        let fields = [
+            (KeyField::Run, id.run, self.run_bits),
+            (KeyField::Rank, id.rank, self.rank_bits),
+            (KeyField::Thread, id.thread, self.thread_bits),
+            (KeyField::Stream, id.stream, self.stream_bits),
+        ];
+        let mut key = 0u128;
+        for &(field, value, bits) in fields.iter() {
+            if bits < 64 && value >> bits != 0 {
+                return Err(KeyError::FieldOverflow { field, value, bits });
+            }
+            key = key << bits | value as u128;
+        }
+        Ok(key)
+    }
+
+    pub fn philox_4x32(&self, id: KeyId) -> Result<Philox4x32, KeyError> {
+        let key = self.pack_within(id, 64)?;
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&key.to_le_bytes()[..8]);
+        Ok(Philox4x32::from_seed(seed))
+    }
+
+    pub fn threefry_2x64(&self, id: KeyId) -> Result<ThreeFry2x64, KeyError> {
+        let key = self.pack_within(id, 128)?;
+        Ok(ThreeFry2x64::from_seed(key.to_le_bytes()))
+    }
+
+    pub fn philox_4x32_rng(&self, id: KeyId) -> Result<Philox4x32Rng, KeyError> {
+        self.philox_4x32(id).map(Philox4x32Rng::from)
+    }
+
+    pub fn threefry_2x64_rng(&self, id: KeyId) -> Result<ThreeFry2x64Rng, KeyError> {
+        self.threefry_2x64(id).map(ThreeFry2x64Rng::from)
+    }
+
+    fn pack_within(&self, id: KeyId, available: u32) -> Result<u128, KeyError> {
+        if self.bits() > available {
+            return Err(KeyError::LayoutTooWide { bits: self.bits(), available });
+        }
+        self.pack(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{KeyAllocator, KeyError, KeyField, KeyId};
+    use philox::Philox4x32;
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, ThreeFry2x64Rng};
+    use rand_core::RngCore;
+
+    #[test]
+    fn layout_validation() {
+        assert!(KeyAllocator::new(16, 16, 16, 16).is_ok());
+        assert!(KeyAllocator::new(64, 64, 0, 0).is_ok());
+        assert_eq!(KeyAllocator::new(0, 65, 0, 0),
+                   Err(KeyError::FieldTooWide { field: KeyField::Rank, bits: 65 }));
+        assert_eq!(KeyAllocator::new(64, 32, 32, 1),
+                   Err(KeyError::LayoutTooWide { bits: 129, available: 128 }));
+    }
+
+    #[test]
+    fn field_overflow() {
+        let alloc = KeyAllocator::new(8, 20, 8, 28).unwrap();
+        let id = KeyId { run: 1, rank: 1 << 20, thread: 0, stream: 0 };
+        assert_eq!(alloc.pack(id),
+                   Err(KeyError::FieldOverflow { field: KeyField::Rank, value: 1 << 20, bits: 20 }));
+        let id = KeyId { run: 0, rank: 0, thread: 0, stream: 1 };
+        let empty = KeyAllocator::new(8, 20, 8, 0).unwrap();
+        assert!(empty.pack(id).is_err());
+        assert!(alloc.philox_4x32_rng(KeyId { thread: 256, ..KeyId::default() }).is_err());
+    }
+
+    #[test]
+    fn target_width() {
+        let alloc = KeyAllocator::new(16, 32, 16, 8).unwrap();
+        let id = KeyId { run: 3, rank: 5, thread: 7, stream: 11 };
+        assert_eq!(alloc.philox_4x32_rng(id).err(),
+                   Some(KeyError::LayoutTooWide { bits: 72, available: 64 }));
+        assert!(alloc.threefry_2x64_rng(id).is_ok());
+    }
+
+    #[test]
+    fn packing() {
+        let alloc = KeyAllocator::new(64, 24, 16, 24).unwrap();
+        let id = KeyId { run: u64::MAX, rank: 0xabcdef, thread: 0x1234, stream: 0x56789a };
+        assert_eq!(alloc.pack(id).unwrap(),
+                   (u64::MAX as u128) << 64 | 0xabcd_ef12_3456_789a);
+
+        let alloc = KeyAllocator::new(8, 20, 8, 28).unwrap();
+        let id = KeyId { run: 0x12, rank: 0x34567, thread: 0x89, stream: 0xabcdef0 };
+        let mut a = alloc.philox_4x32_rng(id).unwrap();
+        let mut b = Philox4x32Rng::from(Philox4x32::from_seed(
+            [0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]));
+        for _ in 0..8 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut seed = [0u8; 16];
+        seed[..8].copy_from_slice(&[0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
+        let mut a = alloc.threefry_2x64_rng(id).unwrap();
+        let mut b = ThreeFry2x64Rng::from(ThreeFry2x64::from_seed(seed));
+        for _ in 0..8 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn disjoint_keys() {
+        let alloc = KeyAllocator::new(2, 3, 2, 1).unwrap();
+        let mut keys = HashSet::new();
+        for run in 0..4 {
+            for rank in 0..8 {
+                for thread in 0..4 {
+                    for stream in 0..2 {
+                        let id = KeyId { run, rank, thread, stream };
+                        assert!(keys.insert(alloc.pack(id).unwrap()));
+                    }
+                }
+            }
+        }
+        assert_eq!(keys.len(), 256);
+    }
+}
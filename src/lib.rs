@@ -1,6 +1,31 @@
 extern crate rand_core;
 extern crate core;
+#[cfg(feature = "bigint")]
+extern crate num_bigint;
 
+#[macro_use]
+pub mod counter;
 pub mod threefry;
 pub mod philox;
 pub mod rng;
+pub mod keys;
+pub mod u01;
+pub mod grid;
+pub mod threefish;
+pub mod skein;
+pub mod permutation;
+pub mod ints;
+pub mod distributions;
+pub mod sampling;
+pub mod ml;
+pub mod brownian;
+pub mod sobol;
+pub mod design;
+pub mod thermostat;
+pub mod noise;
+pub mod lsh;
+pub mod matrix;
+pub mod hash;
+pub mod ids;
+#[cfg(feature = "bigint")]
+pub mod bigint;
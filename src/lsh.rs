@@ -0,0 +1,240 @@
+//! Hash families for similarity estimation and locality-sensitive hashing.
+//!
+//! Every family is defined by a key: function `j` of a family applied to
+//! `x` is the block of `philox_2x64` or `threefry_2x64` at counter `[x, j]`,
+//! so signatures computed anywhere with the same key are comparable and
+//! nothing is stored but the key.
+
+use std::f64::consts::PI;
+
+use super::philox::{philox_2x64, Array1x64};
+use super::threefry::{threefry_2x64, Array2x64};
+use super::u01::{u01_f64, u01_open_f64};
+
+/// Keyed block function defining a hash family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashFamily {
+    Philox2x64(Array1x64),
+    ThreeFry2x64(Array2x64),
+}
+
+impl HashFamily {
+    /// Function `j` of the family applied to `x`.
+    #[inline]
+    pub fn hash(&self, j: u64, x: u64) -> Array2x64 {
+        match *self {
+            HashFamily::Philox2x64(key) => philox_2x64([x, j], key),
+            HashFamily::ThreeFry2x64(key) => {
+                let mut out = [0u64; 2];
+                threefry_2x64([x, j], key, &mut out);
+                out
+            }
+        }
+    }
+}
+
+/// MinHash signatures of sets of `u64` item ids: entry `j` is the least
+/// value of function `j` over the set, so two signatures agree in each
+/// entry with probability equal to the Jaccard similarity of their sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinHash {
+    family: HashFamily,
+    k: usize,
+}
+
+impl MinHash {
+    pub fn new(family: HashFamily, k: usize) -> Self {
+        Self { family, k }
+    }
+
+    pub fn len(&self) -> usize {
+        self.k
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.k == 0
+    }
+
+    /// Signature of the set of `items`; repeated items are harmless and an
+    /// empty set gives all `u64::MAX`.
+    pub fn signature<I: IntoIterator<Item = u64>>(&self, items: I) -> Vec<u64> {
+        let mut sig = vec![u64::MAX; self.k];
+        for x in items {
+            for (j, s) in sig.iter_mut().enumerate() {
+                *s = (*s).min(self.family.hash(j as u64, x)[0]);
+            }
+        }
+        sig
+    }
+}
+
+/// Fraction of matching entries of two MinHash signatures.
+pub fn estimate_jaccard(a: &[u64], b: &[u64]) -> f64 {
+    assert_eq!(a.len(), b.len(), "signatures have different lengths");
+    let same = a.iter().zip(b).filter(|&(x, y)| x == y).count();
+    same as f64 / a.len() as f64
+}
+
+/// Charikar's SimHash: bit `b` of the fingerprint is the sign of the
+/// weighted sum of bit `b` of each feature's hash, taken as `±1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimHash {
+    family: HashFamily,
+}
+
+impl SimHash {
+    pub fn new(family: HashFamily) -> Self {
+        Self { family }
+    }
+
+    /// Fingerprint of weighted `(feature, weight)` pairs.
+    pub fn fingerprint<I: IntoIterator<Item = (u64, f64)>>(&self, features: I) -> u64 {
+        let mut sums = [0.0; 64];
+        for (x, w) in features {
+            let h = self.family.hash(0, x)[0];
+            for (b, s) in sums.iter_mut().enumerate() {
+                if h >> b & 1 == 1 {
+                    *s += w;
+                } else {
+                    *s -= w;
+                }
+            }
+        }
+        sums.iter().enumerate().fold(0, |f, (b, &s)| if s > 0.0 { f | 1 << b } else { f })
+    }
+}
+
+/// Number of differing bits between two fingerprints or sketches.
+pub fn hamming(a: &[u64], b: &[u64]) -> u32 {
+    assert_eq!(a.len(), b.len(), "sketches have different lengths");
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Sign random projections: bit `r` of a vector's sketch is the sign of its
+/// dot product with row `r` of a Gaussian matrix whose entries are
+/// recomputed from `(key, row, col)` on every use. The fraction of differing
+/// bits estimates the angle between two vectors over `π`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignProjection {
+    family: HashFamily,
+    bits: usize,
+}
+
+impl SignProjection {
+    pub fn new(family: HashFamily, bits: usize) -> Self {
+        Self { family, bits }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Standard normal entry of the projection matrix, by Box–Muller on
+    /// the two words of the block.
+    pub fn entry(&self, row: u64, col: u64) -> f64 {
+        let w = self.family.hash(row, col);
+        (-2.0 * u01_open_f64(w[0]).ln()).sqrt() * (2.0 * PI * u01_f64(w[1])).cos()
+    }
+
+    /// Sketch of `x`, packed 64 bits to a word with row `r` in bit `r % 64`
+    /// of word `r / 64`.
+    pub fn sketch(&self, x: &[f64]) -> Vec<u64> {
+        let mut words = vec![0u64; self.bits.div_ceil(64)];
+        for r in 0..self.bits {
+            let dot: f64 = x.iter().enumerate().map(|(c, &v)| v * self.entry(r as u64, c as u64)).sum();
+            if dot > 0.0 {
+                words[r / 64] |= 1 << (r % 64);
+            }
+        }
+        words
+    }
+
+    /// Cosine similarity estimated from two sketches.
+    pub fn estimate_cosine(&self, a: &[u64], b: &[u64]) -> f64 {
+        (PI * hamming(a, b) as f64 / self.bits as f64).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashFamily, MinHash, SimHash, SignProjection, estimate_jaccard, hamming};
+    use distributions::{ks_test, normal_cdf};
+
+    fn families() -> [HashFamily; 2] {
+        [HashFamily::Philox2x64([0x4d1b]), HashFamily::ThreeFry2x64([3, 0x51])]
+    }
+
+    #[test]
+    fn jaccard_converges() {
+        // |A ∩ B| = 500 and |A ∪ B| = 1500
+        let a = 0..1000u64;
+        let b = 500..1500u64;
+        for &family in families().iter() {
+            let mut errors = Vec::new();
+            for &k in [16, 256, 4096].iter() {
+                let m = MinHash::new(family, k);
+                let e = estimate_jaccard(&m.signature(a.clone()), &m.signature(b.clone()));
+                errors.push((e - 1.0 / 3.0).abs());
+                // standard error sqrt(J (1 - J) / k)
+                assert!(errors[errors.len() - 1] < 4.0 * (2.0 / 9.0 / k as f64).sqrt(), "{} {}", k, e);
+            }
+            assert!(errors[2] < 0.02);
+        }
+        let m = MinHash::new(families()[0], 64);
+        assert_eq!(estimate_jaccard(&m.signature(vec![1, 2, 3, 2]), &m.signature(vec![3, 1, 2])), 1.0);
+        assert_eq!(m.signature(None), vec![u64::MAX; 64]);
+    }
+
+    #[test]
+    fn simhash_distances() {
+        let s = SimHash::new(families()[1]);
+        let doc: Vec<(u64, f64)> = (0..200).map(|i| (i, 1.0 + (i % 7) as f64)).collect();
+        let mut near = doc.clone();
+        near[10].1 += 2.0;
+        near.push((999, 1.0));
+        let far: Vec<(u64, f64)> = (1000..1200).map(|i| (i, 1.0)).collect();
+        let f = s.fingerprint(doc.iter().cloned());
+        assert_eq!(f, s.fingerprint(doc.iter().rev().cloned()));
+        let d_near = hamming(&[f], &[s.fingerprint(near)]);
+        let d_far = hamming(&[f], &[s.fingerprint(far)]);
+        assert!(d_near < 6 && d_far > 16, "{} {}", d_near, d_far);
+    }
+
+    #[test]
+    fn projection_entries() {
+        let p = SignProjection::new(families()[0], 64);
+        ks_test((0..5000).map(|c| p.entry(3, c)).collect(), normal_cdf);
+        ks_test((0..5000).map(|r| p.entry(r, 3)).collect(), normal_cdf);
+        assert_eq!(p.entry(10, 20), p.entry(10, 20));
+    }
+
+    #[test]
+    fn cosine_converges() {
+        // two vectors at 60 degrees in a 16-dimensional space
+        let mut a = vec![0.0; 16];
+        let mut b = vec![0.0; 16];
+        a[0] = 1.0;
+        b[0] = 0.5;
+        b[5] = 0.75f64.sqrt();
+        for &family in families().iter() {
+            let p = SignProjection::new(family, 4096);
+            let (sa, sb) = (p.sketch(&a), p.sketch(&b));
+            assert_eq!(sa.len(), 64);
+            assert!((p.estimate_cosine(&sa, &sb) - 0.5).abs() < 0.05);
+            // the sketch only sees directions
+            let scaled: Vec<f64> = a.iter().map(|x| 3.0 * x).collect();
+            assert_eq!(p.sketch(&scaled), sa);
+        }
+    }
+
+    #[test]
+    fn golden_values() {
+        let m = MinHash::new(families()[0], 3);
+        assert_eq!(m.signature(0..10), [927897353037891321, 679372709148498369, 765533097467183519]);
+        assert_eq!(SimHash::new(families()[1]).fingerprint(vec![(1, 1.0), (2, 2.0)]),
+                   6252141214038616687);
+        let p = SignProjection::new(families()[1], 70);
+        assert_eq!(p.sketch(&[1.0, -2.0, 0.5]), [13787117763179741911, 0]);
+        assert_eq!(p.sketch(&[-1.0, 2.0, -0.5]), [!13787117763179741911, 63]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Random matrices that are never stored.
+//!
+//! Entry `(i, j)` is a pure function of the key and its indices: the block of
+//! `philox_4x32` at counter `[i, j]`, split into 32-bit halves. Any tile of
+//! a matrix can be generated alone, and products are summed in column order
+//! within each row, so splitting the rows among tiles or workers gives
+//! bitwise identical results.
+
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use super::philox::{philox_4x32, Array2x32};
+use super::u01::{u01_f64, u01_open_f64};
+
+/// Distribution of the entries, each with mean 0 and variance 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Entries {
+    /// Standard normal.
+    Gaussian,
+    /// `±1` with equal probability.
+    Rademacher,
+    /// Achlioptas–Li sparse signs: `±sqrt(s)` with probability `1 / (2s)`
+    /// each and 0 otherwise, for `s >= 1`.
+    SparseSign(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RandomMatrix {
+    key: Array2x32,
+    rows: usize,
+    cols: usize,
+    entries: Entries,
+}
+
+impl RandomMatrix {
+    pub fn new(key: Array2x32, rows: usize, cols: usize, entries: Entries) -> Self {
+        if let Entries::SparseSign(s) = entries {
+            assert!(s >= 1.0, "sparsity {} is less than 1", s);
+        }
+        Self { key, rows, cols, entries }
+    }
+
+    pub fn gaussian(key: Array2x32, rows: usize, cols: usize) -> Self {
+        Self::new(key, rows, cols, Entries::Gaussian)
+    }
+
+    pub fn rademacher(key: Array2x32, rows: usize, cols: usize) -> Self {
+        Self::new(key, rows, cols, Entries::Rademacher)
+    }
+
+    pub fn sparse_sign(key: Array2x32, rows: usize, cols: usize, s: f64) -> Self {
+        Self::new(key, rows, cols, Entries::SparseSign(s))
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Entry `(i, j)`; the indices are not checked against the shape, so a
+    /// matrix can be grown without changing its existing entries.
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        let (i, j) = (i as u64, j as u64);
+        let w = philox_4x32([i as u32, (i >> 32) as u32, j as u32, (j >> 32) as u32], self.key);
+        let lo = (w[1] as u64) << 32 | w[0] as u64;
+        match self.entries {
+            Entries::Gaussian => {
+                let hi = (w[3] as u64) << 32 | w[2] as u64;
+                (-2.0 * u01_open_f64(lo).ln()).sqrt() * (2.0 * PI * u01_f64(hi)).cos()
+            }
+            Entries::Rademacher => if lo >> 63 == 1 { -1.0 } else { 1.0 },
+            Entries::SparseSign(s) => {
+                let u = u01_f64(lo) * s;
+                if u < 0.5 {
+                    s.sqrt()
+                } else if u < 1.0 {
+                    -s.sqrt()
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Writes the tile of `rows` by `cols`, row-major, to `out`.
+    pub fn block(&self, rows: Range<usize>, cols: Range<usize>, out: &mut [f64]) {
+        let width = cols.len();
+        assert_eq!(out.len(), rows.len() * width, "tile buffer has the wrong length");
+        for (r, i) in rows.enumerate() {
+            for (c, j) in cols.clone().enumerate() {
+                out[r * width + c] = self.get(i, j);
+            }
+        }
+    }
+
+    /// `y = A[rows, :] x` for the given range of rows.
+    pub fn mul_rows(&self, rows: Range<usize>, x: &[f64], y: &mut [f64]) {
+        assert_eq!(x.len(), self.cols, "vector length is not the column count");
+        assert_eq!(y.len(), rows.len(), "output length is not the row count");
+        for (y, i) in y.iter_mut().zip(rows) {
+            *y = x.iter().enumerate().map(|(j, &x)| self.get(i, j) * x).sum();
+        }
+    }
+
+    /// `y = A x`.
+    pub fn mul_vec(&self, x: &[f64], y: &mut [f64]) {
+        self.mul_rows(0..self.rows, x, y)
+    }
+
+    /// `y = A^T x`, summing over rows in order.
+    pub fn mul_vec_transpose(&self, x: &[f64], y: &mut [f64]) {
+        assert_eq!(x.len(), self.rows, "vector length is not the row count");
+        assert_eq!(y.len(), self.cols, "output length is not the column count");
+        for (j, y) in y.iter_mut().enumerate() {
+            *y = x.iter().enumerate().map(|(i, &x)| self.get(i, j) * x).sum();
+        }
+    }
+
+    /// `C = A[rows, :] B` for row-major `B` with `k` columns, into row-major
+    /// `c` of `rows.len()` by `k`.
+    pub fn mul_mat_rows(&self, rows: Range<usize>, b: &[f64], k: usize, c: &mut [f64]) {
+        assert_eq!(b.len(), self.cols * k, "right factor has the wrong shape");
+        assert_eq!(c.len(), rows.len() * k, "product buffer has the wrong length");
+        for v in c.iter_mut() {
+            *v = 0.0;
+        }
+        for (r, i) in rows.enumerate() {
+            let out = &mut c[r * k..(r + 1) * k];
+            for j in 0..self.cols {
+                let a = self.get(i, j);
+                for (o, &b) in out.iter_mut().zip(&b[j * k..(j + 1) * k]) {
+                    *o += a * b;
+                }
+            }
+        }
+    }
+
+    /// `C = A B`.
+    pub fn mul_mat(&self, b: &[f64], k: usize, c: &mut [f64]) {
+        self.mul_mat_rows(0..self.rows, b, k, c)
+    }
+}
+
+/// An `n` by `k` matrix, row-major, with orthonormal columns distributed
+/// by Haar measure: the Q factor of the `n` by `k` Gaussian matrix under
+/// `key`. Gram–Schmidt, applied twice for stability, gives R a positive
+/// diagonal, which makes Q exactly Haar rather than biased by the signs a
+/// Householder QR would choose. For `k == n` the result is Haar on O(n).
+pub fn haar_orthogonal(key: Array2x32, n: usize, k: usize) -> Vec<f64> {
+    assert!(k <= n, "cannot have {} orthonormal columns in dimension {}", k, n);
+    let g = RandomMatrix::gaussian(key, n, k);
+    // columns as contiguous vectors while orthogonalizing
+    let mut q: Vec<Vec<f64>> = (0..k).map(|j| (0..n).map(|i| g.get(i, j)).collect()).collect();
+    for j in 0..k {
+        let (done, rest) = q.split_at_mut(j);
+        let v = &mut rest[0];
+        for _ in 0..2 {
+            for u in done.iter() {
+                let dot: f64 = u.iter().zip(v.iter()).map(|(a, b)| a * b).sum();
+                for (x, &y) in v.iter_mut().zip(u) {
+                    *x -= dot * y;
+                }
+            }
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        for x in v.iter_mut() {
+            *x /= norm;
+        }
+    }
+    let mut out = vec![0.0; n * k];
+    for (j, col) in q.iter().enumerate() {
+        for (i, &x) in col.iter().enumerate() {
+            out[i * k + j] = x;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RandomMatrix, haar_orthogonal};
+    use distributions::{ks_test, normal_cdf};
+
+    const KEY: [u32; 2] = [0x3a7, 0x91];
+
+    #[test]
+    fn tiles() {
+        let a = RandomMatrix::gaussian(KEY, 37, 23);
+        let mut whole = vec![0.0; 37 * 23];
+        a.block(0..37, 0..23, &mut whole);
+        let mut tile = vec![0.0; 5 * 7];
+        a.block(10..15, 3..10, &mut tile);
+        for r in 0..5 {
+            for c in 0..7 {
+                assert_eq!(tile[r * 7 + c], whole[(10 + r) * 23 + 3 + c]);
+            }
+        }
+
+        let x: Vec<f64> = (0..23).map(|j| (j as f64).sin()).collect();
+        let mut y = vec![0.0; 37];
+        a.mul_vec(&x, &mut y);
+        let mut tiled = vec![0.0; 37];
+        for start in (0..37).step_by(8) {
+            let end = (start + 8).min(37);
+            a.mul_rows(start..end, &x, &mut tiled[start..end]);
+        }
+        assert_eq!(y, tiled);
+
+        let b: Vec<f64> = (0..23 * 3).map(|j| (j as f64).cos()).collect();
+        let mut c = vec![0.0; 37 * 3];
+        a.mul_mat(&b, 3, &mut c);
+        let mut c2 = vec![0.0; 20 * 3];
+        a.mul_mat_rows(17..37, &b, 3, &mut c2);
+        assert_eq!(&c[17 * 3..], &c2[..]);
+        for i in 0..37 {
+            let expected: f64 = (0..23).map(|j| whole[i * 23 + j] * b[j * 3 + 1]).sum();
+            assert!((c[i * 3 + 1] - expected).abs() < 1e-12);
+        }
+
+        let mut t = vec![0.0; 23];
+        a.mul_vec_transpose(&y, &mut t);
+        let expected: f64 = (0..37).map(|i| whole[i * 23 + 4] * y[i]).sum();
+        assert!((t[4] - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn entry_distributions() {
+        let g = RandomMatrix::gaussian(KEY, 100, 100);
+        ks_test((0..10000).map(|n| g.get(n / 100, n % 100)).collect(), normal_cdf);
+
+        let r = RandomMatrix::rademacher(KEY, 100, 100);
+        let values: Vec<f64> = (0..10000).map(|n| r.get(n / 100, n % 100)).collect();
+        assert!(values.iter().all(|&v| v == 1.0 || v == -1.0));
+        assert!(values.iter().sum::<f64>().abs() < 300.0);
+
+        let s = RandomMatrix::sparse_sign(KEY, 100, 100, 9.0);
+        let values: Vec<f64> = (0..10000).map(|n| s.get(n / 100, n % 100)).collect();
+        let nonzero = values.iter().filter(|&&v| v != 0.0).count();
+        assert!((nonzero as f64 - 10000.0 / 9.0).abs() < 100.0, "{}", nonzero);
+        assert!(values.iter().all(|&v| v == 0.0 || v.abs() == 3.0));
+        let var = values.iter().map(|v| v * v).sum::<f64>() / 10000.0;
+        assert!((var - 1.0).abs() < 0.1, "{}", var);
+    }
+
+    #[test]
+    fn johnson_lindenstrauss() {
+        // a k-row sketch scaled by 1 / sqrt(k) keeps norms to about 1 / sqrt(k)
+        let (k, d) = (400, 1000);
+        let x: Vec<f64> = (0..d).map(|j| ((j * 7 % 13) as f64 - 6.0) / 10.0).collect();
+        let norm2: f64 = x.iter().map(|v| v * v).sum();
+        for a in [RandomMatrix::gaussian(KEY, k, d), RandomMatrix::rademacher(KEY, k, d),
+                  RandomMatrix::sparse_sign(KEY, k, d, 3.0)].iter() {
+            let mut y = vec![0.0; k];
+            a.mul_vec(&x, &mut y);
+            let ratio = y.iter().map(|v| v * v).sum::<f64>() / k as f64 / norm2;
+            assert!((ratio - 1.0).abs() < 0.25, "{:?} {}", a, ratio);
+        }
+    }
+
+    #[test]
+    fn haar() {
+        let (n, k) = (12, 5);
+        let q = haar_orthogonal(KEY, n, k);
+        for a in 0..k {
+            for b in 0..k {
+                let dot: f64 = (0..n).map(|i| q[i * k + a] * q[i * k + b]).sum();
+                let expected = if a == b { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-13, "{} {} {}", a, b, dot);
+            }
+        }
+        // under Haar measure on O(3) each entry is uniform on [-1, 1]
+        let samples = (0..3000u32).map(|s| haar_orthogonal([s, 1], 3, 3)[0]).collect();
+        ks_test(samples, |x| 0.5 * (x + 1.0));
+    }
+
+    #[test]
+    fn golden_values() {
+        let g = RandomMatrix::gaussian(KEY, 4, 4);
+        assert_eq!([g.get(0, 0), g.get(3, 1)], [-0.1585176619145411, 0.514183529096859]);
+        let s = RandomMatrix::sparse_sign(KEY, 4, 4, 2.0);
+        let mut t = [0.0; 4];
+        s.block(1..3, 1..3, &mut t);
+        assert_eq!(t, [0.0, 0.0, 0.0, -2f64.sqrt()]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Stochastic rounding and dropout masks for mixed-precision training.
+//!
+//! Every element draws one 32-bit lane of `philox_4x32`, found from the
+//! element's absolute index, so the same values can be regenerated in the
+//! backward pass and any split of a tensor into chunks gives the same
+//! result as processing it whole.
+
+use super::philox::{philox_4x32, Array4x32};
+
+const PURPOSE_ROUNDING: u32 = 0;
+const PURPOSE_DROPOUT: u32 = 1;
+
+/// Identifies the random numbers for one tensor at one training step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TensorKey {
+    pub seed: u64,
+    pub layer: u32,
+    pub step: u32,
+}
+
+impl TensorKey {
+    pub fn new(seed: u64, layer: u32, step: u32) -> Self {
+        Self { seed, layer, step }
+    }
+
+    /// Block holding the lanes of elements `4 * block` to `4 * block + 3`.
+    /// The top two bits of the second counter word separate rounding from
+    /// dropout, so both can use the same key.
+    fn block(&self, purpose: u32, block: u64) -> Array4x32 {
+        let ctr = [block as u32, (block >> 32) as u32 | purpose << 30, self.layer, self.step];
+        philox_4x32(ctr, [self.seed as u32, (self.seed >> 32) as u32])
+    }
+
+    /// Calls `f` with the lane of each element in `start..start + len`.
+    fn for_each_lane<F: FnMut(usize, u32)>(&self, purpose: u32, start: u64, len: usize, mut f: F) {
+        let mut block = self.block(purpose, start / 4);
+        for i in 0..len {
+            let index = start + i as u64;
+            if index.is_multiple_of(4) && i > 0 {
+                block = self.block(purpose, index / 4);
+            }
+            f(i, block[(index % 4) as usize]);
+        }
+    }
+}
+
+/// Rounds `x` to bfloat16 up or down with probabilities proportional to its
+/// distance from each neighbour, using the low 16 bits of `r`.
+pub fn stochastic_bf16(x: f32, r: u32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        // keep the sign and force a quiet NaN
+        return (bits >> 16) as u16 | 0x0040;
+    }
+    // a carry out of the low half rounds the magnitude up, and infinities
+    // have no low bits to carry
+    ((bits + (r & 0xffff)) >> 16) as u16
+}
+
+/// Rounds `x` stochastically to IEEE half precision, using the low 13 bits
+/// of `r` for normal results and the high 24 bits for subnormal ones.
+pub fn stochastic_f16(x: f32, r: u32) -> u16 {
+    let bits = x.to_bits();
+    let sign = (bits >> 16) as u16 & 0x8000;
+    let abs = bits & 0x7fff_ffff;
+    if abs >= 0x7f80_0000 {
+        return sign | 0x7c00 | if abs > 0x7f80_0000 { 0x0200 } else { 0 };
+    }
+    if abs >= 0x3880_0000 {
+        // at least 2^-14: rebias the exponent from 127 to 15 and drop 13
+        // mantissa bits, saturating at infinity
+        let m = abs - 0x3800_0000;
+        return sign | ((m + (r & 0x1fff)) >> 13).min(0x7c00) as u16;
+    }
+    // subnormal results count multiples of 2^-24; complementing `r` keeps
+    // large values rounding up as in the normal case
+    let v = f32::from_bits(abs) * 16_777_216.0;
+    let low = v.floor();
+    let up = ((!r >> 8) as f32 / 16_777_216.0) < v - low;
+    sign | (low as u16 + up as u16)
+}
+
+/// Rounds `x` stochastically to single precision, using the low 29 bits of
+/// `r` for normal results and all of it for subnormal ones.
+pub fn stochastic_f32(x: f64, r: u32) -> f32 {
+    let bits = x.to_bits();
+    let sign = (bits >> 32) as u32 & 0x8000_0000;
+    let abs = bits & 0x7fff_ffff_ffff_ffff;
+    if abs >= 0x7ff0_0000_0000_0000 {
+        return if x.is_nan() { x as f32 } else { f32::from_bits(sign | 0x7f80_0000) };
+    }
+    let h = if abs >= 0x3810_0000_0000_0000 {
+        // at least 2^-126: rebias the exponent from 1023 to 127 and drop 29
+        // mantissa bits
+        let m = abs - 0x3800_0000_0000_0000;
+        ((m + (r as u64 & 0x1fff_ffff)) >> 29).min(0x7f80_0000) as u32
+    } else {
+        // subnormal results count multiples of 2^-149
+        let v = f64::from_bits(abs) * 2f64.powi(149);
+        let low = v.floor();
+        low as u32 + ((!r as f64 / 4_294_967_296.0) < v - low) as u32
+    };
+    f32::from_bits(sign | h)
+}
+
+/// Stochastically rounds `src`, the elements of the tensor starting at index
+/// `start`, to bfloat16 bit patterns in `dst`.
+pub fn round_bf16(key: &TensorKey, start: u64, src: &[f32], dst: &mut [u16]) {
+    assert_eq!(src.len(), dst.len(), "source and destination lengths differ");
+    key.for_each_lane(PURPOSE_ROUNDING, start, src.len(), |i, r| {
+        dst[i] = stochastic_bf16(src[i], r);
+    });
+}
+
+/// Like `round_bf16`, to IEEE half precision bit patterns.
+pub fn round_f16(key: &TensorKey, start: u64, src: &[f32], dst: &mut [u16]) {
+    assert_eq!(src.len(), dst.len(), "source and destination lengths differ");
+    key.for_each_lane(PURPOSE_ROUNDING, start, src.len(), |i, r| {
+        dst[i] = stochastic_f16(src[i], r);
+    });
+}
+
+/// Like `round_bf16`, from double to single precision.
+pub fn round_f32(key: &TensorKey, start: u64, src: &[f64], dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len(), "source and destination lengths differ");
+    key.for_each_lane(PURPOSE_ROUNDING, start, src.len(), |i, r| {
+        dst[i] = stochastic_f32(src[i], r);
+    });
+}
+
+/// Fills `mask` with Bernoulli(`p`) bits for the elements starting at index
+/// `start`, which must be a multiple of 64: element `start + i` is bit
+/// `i % 64` of `mask[i / 64]`. `p` is resolved to a multiple of 2^-32.
+pub fn dropout_mask(key: &TensorKey, start: u64, p: f64, mask: &mut [u64]) {
+    assert!(start.is_multiple_of(64), "mask must start on a word boundary, got {}", start);
+    assert!((0.0..=1.0).contains(&p), "probability {} is not in [0, 1]", p);
+    let threshold = (p * 4_294_967_296.0).round() as u64;
+    for w in mask.iter_mut() {
+        *w = 0;
+    }
+    key.for_each_lane(PURPOSE_DROPOUT, start, 64 * mask.len(), |i, r| {
+        if (r as u64) < threshold {
+            mask[i / 64] |= 1 << (i % 64);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stochastic_bf16, stochastic_f16, stochastic_f32};
+    use super::{round_bf16, round_f16, round_f32, dropout_mask, TensorKey};
+
+    fn bf16_to_f32(h: u16) -> f32 {
+        f32::from_bits((h as u32) << 16)
+    }
+
+    fn f16_to_f32(h: u16) -> f32 {
+        let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exp = (h >> 10) & 0x1f;
+        let mant = (h & 0x3ff) as f32;
+        sign * match exp {
+            0 => mant * 2f32.powi(-24),
+            31 => if mant == 0.0 { f32::INFINITY } else { f32::NAN },
+            _ => (1.0 + mant / 1024.0) * 2f32.powi(exp as i32 - 15),
+        }
+    }
+
+    #[test]
+    fn neighbours_only() {
+        let x = 1.0 + 1.0 / 3.0;
+        for &r in [0, 1, 0x5555, 0xaaaa, 0xffff, u32::MAX].iter() {
+            let b = bf16_to_f32(stochastic_bf16(x, r));
+            assert!(b == 1.328125 || b == 1.3359375, "{}", b);
+            let h = f16_to_f32(stochastic_f16(x, r));
+            assert!(h == 1.3330078 || h == 1.3339844, "{}", h);
+            let f = stochastic_f32(x as f64, r);
+            assert!(f == x || f == f32::from_bits(x.to_bits() + 1) || f == f32::from_bits(x.to_bits() - 1));
+        }
+        assert_eq!(bf16_to_f32(stochastic_bf16(-2.5, 0xffff)), -2.5);
+        assert_eq!(f16_to_f32(stochastic_f16(-0.375, 0xffff_ffff)), -0.375);
+        assert_eq!(stochastic_f32(0.1f32 as f64, u32::MAX), 0.1f32);
+    }
+
+    #[test]
+    fn special_values() {
+        assert_eq!(stochastic_bf16(f32::INFINITY, u32::MAX), 0x7f80);
+        assert!(bf16_to_f32(stochastic_bf16(f32::NAN, 0)).is_nan());
+        assert_eq!(stochastic_f16(f32::NEG_INFINITY, 0), 0xfc00);
+        assert!(f16_to_f32(stochastic_f16(f32::NAN, 0)).is_nan());
+        assert_eq!(stochastic_f16(1e6, 0), 0x7c00);
+        assert_eq!(stochastic_f16(65504.0, 0), 0x7bff);
+        assert_eq!(stochastic_f16(0.0, u32::MAX), 0);
+        assert_eq!(stochastic_f16(-0.0, u32::MAX), 0x8000);
+        assert_eq!(stochastic_f32(1e300, 0), f32::INFINITY);
+        assert!(stochastic_f32(f64::NAN, 0).is_nan());
+        assert_eq!(stochastic_f32(-f64::INFINITY, 0), f32::NEG_INFINITY);
+        // the smallest subnormals round to zero or up, never elsewhere
+        let tiny = 2f64.powi(-151);
+        assert_eq!(stochastic_f32(tiny, 0), 0.0);
+        assert_eq!(stochastic_f32(tiny, u32::MAX), f32::from_bits(1));
+        assert_eq!(stochastic_f16(2f32.powi(-26), 0), 0);
+        assert_eq!(stochastic_f16(2f32.powi(-26), u32::MAX), 1);
+    }
+
+    #[test]
+    fn unbiased() {
+        // the mean of many stochastic roundings is the unrounded value
+        let n = 40000;
+        let key = TensorKey::new(42, 3, 9);
+        for &x in [1.0 + 1.0 / 3.0, -7.123_456, 3e-6, 1e-39].iter() {
+            let src = vec![x; n];
+            let mut dst = vec![0u16; n];
+            round_bf16(&key, 0, &src, &mut dst);
+            let mean = dst.iter().map(|&h| bf16_to_f32(h) as f64).sum::<f64>() / n as f64;
+            let down = (x.abs().to_bits() >> 16) as u16;
+            let ulp = (bf16_to_f32(down + 1) - bf16_to_f32(down)) as f64;
+            assert!((mean - x as f64).abs() < 0.02 * ulp, "{} {}", x, mean);
+
+            round_f16(&key, 0, &src, &mut dst);
+            let mean = dst.iter().map(|&h| f16_to_f32(h) as f64).sum::<f64>() / n as f64;
+            assert!((mean - x as f64).abs() < 2e-3 * (x.abs() as f64).max(6e-8), "{} {}", x, mean);
+        }
+        let x = 0.1f64;
+        let src = vec![x; n];
+        let mut dst = vec![0f32; n];
+        round_f32(&key, 0, &src, &mut dst);
+        let mean = dst.iter().map(|&f| f as f64).sum::<f64>() / n as f64;
+        let ulp = (f32::from_bits(0.1f32.to_bits() + 1) - 0.1f32) as f64;
+        assert!((mean - x).abs() < 0.02 * ulp, "{}", (mean - x) / ulp);
+    }
+
+    #[test]
+    fn chunking() {
+        let key = TensorKey::new(0xfeed, 1, 100);
+        let src: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.37).sin() * 10.0).collect();
+        let mut whole = vec![0u16; 1000];
+        round_bf16(&key, 0, &src, &mut whole);
+        let mut chunked = vec![0u16; 1000];
+        let mut at = 0;
+        for &len in [1, 2, 3, 7, 64, 200, 723].iter() {
+            round_bf16(&key, at as u64, &src[at..at + len], &mut chunked[at..at + len]);
+            at += len;
+        }
+        assert_eq!(whole, chunked);
+
+        // a later step draws fresh numbers
+        let mut next = vec![0u16; 1000];
+        round_bf16(&TensorKey::new(0xfeed, 1, 101), 0, &src, &mut next);
+        assert!(whole != next);
+
+        let mut whole = vec![0u64; 8];
+        dropout_mask(&key, 128, 0.3, &mut whole);
+        let mut chunked = vec![0u64; 8];
+        dropout_mask(&key, 128, 0.3, &mut chunked[..3]);
+        dropout_mask(&key, 128 + 3 * 64, 0.3, &mut chunked[3..]);
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn dropout_rate() {
+        let key = TensorKey::new(7, 0, 0);
+        let mut mask = vec![0u64; 1000];
+        dropout_mask(&key, 0, 0.1, &mut mask);
+        let ones: u32 = mask.iter().map(|w| w.count_ones()).sum();
+        assert!((ones as f64 / 64000.0 - 0.1).abs() < 0.005, "{}", ones);
+        dropout_mask(&key, 0, 0.0, &mut mask);
+        assert!(mask.iter().all(|&w| w == 0));
+        dropout_mask(&key, 0, 1.0, &mut mask);
+        assert!(mask.iter().all(|&w| w == u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "word boundary")]
+    fn dropout_alignment() {
+        dropout_mask(&TensorKey::new(7, 0, 0), 32, 0.5, &mut [0]);
+    }
+
+    #[test]
+    fn golden() {
+        let key = TensorKey::new(1, 2, 3);
+        let mut bf = [0u16; 6];
+        round_bf16(&key, 10, &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], &mut bf);
+        assert_eq!(bf, [15820, 15949, 16025, 16077, 16128, 16153]);
+        let mut mask = [0u64; 1];
+        dropout_mask(&key, 64, 0.5, &mut mask);
+        assert_eq!(mask, [16101541507498464839]);
+    }
+}
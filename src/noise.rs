@@ -0,0 +1,481 @@
+//! Lattice noise for procedural generation: value, gradient (Perlin),
+//! simplex and Worley cellular noise in two to four dimensions, with
+//! fractal octave sums.
+//!
+//! Every lattice point is hashed by a block function under the world seed:
+//! `philox_2x32` at counter `[x, y]` in two dimensions and `philox_4x32` at
+//! counter `[x, y, z, w]` in three and four, so the 3D lattice is the `w = 0`
+//! slice of the 4D one. Noise is therefore unbounded in extent, can be
+//! evaluated anywhere without state, and is the same on every platform.
+//! Cell coordinates are 32-bit and wrap beyond `±2^31`.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use super::philox::{philox_2x32, philox_4x32, Array1x32, Array2x32};
+use super::u01::u01_f32;
+
+/// Floating-point types noise can be evaluated in.
+pub trait Float: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self>
+    + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self> {
+    fn of(x: f64) -> Self;
+    fn floor(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn to_i32(self) -> i32;
+    /// Uniform in `[0, 1)` from a hash word.
+    fn unit(x: u32) -> Self;
+}
+
+impl Float for f32 {
+    fn of(x: f64) -> Self { x as f32 }
+    fn floor(self) -> Self { self.floor() }
+    fn sqrt(self) -> Self { self.sqrt() }
+    fn to_i32(self) -> i32 { self as i64 as i32 }
+    fn unit(x: u32) -> Self { u01_f32(x) }
+}
+
+impl Float for f64 {
+    fn of(x: f64) -> Self { x }
+    fn floor(self) -> Self { self.floor() }
+    fn sqrt(self) -> Self { self.sqrt() }
+    fn to_i32(self) -> i32 { self as i64 as i32 }
+    fn unit(x: u32) -> Self { x as f64 / 4_294_967_296.0 }
+}
+
+/// Quintic fade curve `6t^5 - 15t^4 + 10t^3`.
+fn fade<T: Float>(t: T) -> T {
+    t * t * t * (t * (t * T::of(6.0) - T::of(15.0)) + T::of(10.0))
+}
+
+/// Gradient picked by a hash word: the eight compass directions in two
+/// dimensions, otherwise the `n 2^(n-1)` cube edge midpoints (12 or 32).
+fn gradient<T: Float>(n: usize, h: u32) -> [T; 4] {
+    let one = T::of(1.0);
+    let zero = T::of(0.0);
+    let mut g = [zero; 4];
+    if n == 2 {
+        const DIRS: [(f64, f64); 8] = [(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0),
+                                       (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)];
+        let (x, y) = DIRS[((h as u64 * 8) >> 32) as usize];
+        g[0] = T::of(x);
+        g[1] = T::of(y);
+        return g;
+    }
+    let signs = 1u64 << (n - 1);
+    let index = (h as u64 * (n as u64 * signs)) >> 32;
+    let (axis, mut bits) = ((index / signs) as usize, index % signs);
+    for (d, g) in g.iter_mut().enumerate().take(n) {
+        if d != axis {
+            *g = if bits & 1 == 1 { -one } else { one };
+            bits >>= 1;
+        }
+    }
+    g
+}
+
+fn check_dims(n: usize) {
+    assert!((2..=4).contains(&n), "noise is defined in 2 to 4 dimensions, not {}", n);
+}
+
+macro_rules! named {
+    ($core: ident, $out: ty, $($name: ident($($x: ident),+);)+) => {$(
+        pub fn $name<T: Float>(&self, $($x: T),+) -> $out {
+            self.$core(&[$($x),+])
+        }
+    )+}
+}
+
+/// Noise functions of one world seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Noise {
+    key: Array2x32,
+    key2: Array1x32,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let key = [seed as u32, (seed >> 32) as u32];
+        Self { key, key2: [philox_2x32(key, [0x9e37_79b9])[0]] }
+    }
+
+    /// Hash words of a lattice point.
+    fn hash(&self, cell: &[i32]) -> [u32; 4] {
+        if cell.len() == 2 {
+            let h = philox_2x32([cell[0] as u32, cell[1] as u32], self.key2);
+            return [h[0], h[1], 0, 0];
+        }
+        let mut ctr = [0u32; 4];
+        for (c, &x) in ctr.iter_mut().zip(cell) {
+            *c = x as u32;
+        }
+        philox_4x32(ctr, self.key)
+    }
+
+    /// Interpolates `corner(hash, offset)` over the corners of the cell
+    /// containing `p` with fade-curve weights.
+    fn interpolate<T: Float, F: Fn(u32, &[T]) -> T>(&self, p: &[T], corner: F) -> T {
+        let n = p.len();
+        check_dims(n);
+        let zero = T::of(0.0);
+        let one = T::of(1.0);
+        let mut base = [0i32; 4];
+        let mut frac = [zero; 4];
+        let mut weight = [zero; 4];
+        for d in 0..n {
+            let f = p[d].floor();
+            base[d] = f.to_i32();
+            frac[d] = p[d] - f;
+            weight[d] = fade(frac[d]);
+        }
+        let mut sum = zero;
+        for c in 0..1 << n {
+            let mut cell = [0i32; 4];
+            let mut offset = [zero; 4];
+            let mut w = one;
+            for d in 0..n {
+                let bit = c >> d & 1 == 1;
+                cell[d] = base[d].wrapping_add(bit as i32);
+                offset[d] = if bit { frac[d] - one } else { frac[d] };
+                w = w * if bit { weight[d] } else { one - weight[d] };
+            }
+            sum = sum + w * corner(self.hash(&cell[..n])[0], &offset[..n]);
+        }
+        sum
+    }
+
+    /// Value noise in `[-1, 1]`: lattice values interpolated by the fade
+    /// curve, in as many dimensions as `p` has coordinates.
+    pub fn value<T: Float>(&self, p: &[T]) -> T {
+        self.interpolate(p, |h, _| T::of(2.0) * T::unit(h) - T::of(1.0))
+    }
+
+    /// Perlin gradient noise, zero at lattice points and roughly in `[-1, 1]`.
+    pub fn perlin<T: Float>(&self, p: &[T]) -> T {
+        let n = p.len();
+        self.interpolate(p, |h, offset| {
+            let g = gradient::<T>(n, h);
+            (0..n).fold(T::of(0.0), |s, d| s + g[d] * offset[d])
+        })
+    }
+
+    /// Simplex noise, roughly in `[-1, 1]`, summing the kernels of the
+    /// `n + 1` corners of the simplex containing `p`.
+    pub fn simplex<T: Float>(&self, p: &[T]) -> T {
+        let n = p.len();
+        check_dims(n);
+        let (radius, scale) = match n {
+            2 => (0.5, 70.0),
+            3 => (0.6, 32.0),
+            _ => (0.6, 27.0),
+        };
+        let root = ((n + 1) as f64).sqrt();
+        let skew = T::of((root - 1.0) / n as f64);
+        let unskew = (1.0 - 1.0 / root) / n as f64;
+        let zero = T::of(0.0);
+
+        let s = p.iter().fold(zero, |s, &x| s + x) * skew;
+        let mut cell = [0i32; 4];
+        let mut cell_sum = 0i64;
+        for d in 0..n {
+            let f = (p[d] + s).floor();
+            cell[d] = f.to_i32();
+            cell_sum += f.to_i32() as i64;
+        }
+        let t = T::of(cell_sum as f64 * unskew);
+        // the position of p relative to the unskewed cell origin
+        let mut x = [zero; 4];
+        for d in 0..n {
+            x[d] = p[d] - (T::of(cell[d] as f64) - t);
+        }
+        // coordinates step in decreasing order of their offsets
+        let mut rank = [0usize; 4];
+        for d in 0..n {
+            for e in 0..n {
+                if e != d && (x[e] < x[d] || (x[e] == x[d] && e > d)) {
+                    rank[d] += 1;
+                }
+            }
+        }
+        let mut sum = zero;
+        for k in 0..n + 1 {
+            let mut corner = [0i32; 4];
+            let mut v = [zero; 4];
+            let mut r2 = T::of(radius);
+            for d in 0..n {
+                let step = rank[d] + k >= n;
+                corner[d] = cell[d].wrapping_add(step as i32);
+                v[d] = x[d] - T::of(step as u32 as f64) + T::of(k as f64 * unskew);
+                r2 = r2 - v[d] * v[d];
+            }
+            if r2 > zero {
+                let g = gradient::<T>(n, self.hash(&corner[..n])[0]);
+                let dot = (0..n).fold(zero, |s, d| s + g[d] * v[d]);
+                let r4 = r2 * r2;
+                sum = sum + r4 * r4 * dot;
+            }
+        }
+        T::of(scale) * sum
+    }
+
+    /// Worley cellular noise: distances `[F1, F2]` from `p` to the nearest
+    /// and second nearest feature points, with one feature point placed
+    /// uniformly in each lattice cell. Both are exact. The `3^n` cells around
+    /// `p` are searched first, then any cell up to three away that could
+    /// still hold a nearer point; farther cells cannot, since a point in the
+    /// cell of `p` and one in a neighbour give `F2 < sqrt(n + 3) < 3`.
+    pub fn worley<T: Float>(&self, p: &[T]) -> [T; 2] {
+        let n = p.len();
+        check_dims(n);
+        let zero = T::of(0.0);
+        let mut base = [0i32; 4];
+        let mut frac = [zero; 4];
+        for d in 0..n {
+            let f = p[d].floor();
+            base[d] = f.to_i32();
+            frac[d] = p[d] - f;
+        }
+        let far = T::of(f64::MAX);
+        let (mut f1, mut f2) = (far, far);
+        let mut cells = 1;
+        for _ in 0..n {
+            cells *= 7;
+        }
+        for near_pass in [true, false].iter() {
+            for c in 0..cells {
+                let mut cell = [0i32; 4];
+                let mut offset = [0i32; 4];
+                let mut rest = c;
+                let mut near = true;
+                // squared distance from p to the closest corner of the cell
+                let mut gap = zero;
+                for d in 0..n {
+                    offset[d] = rest % 7 - 3;
+                    rest /= 7;
+                    cell[d] = base[d].wrapping_add(offset[d]);
+                    near &= offset[d].abs() <= 1;
+                    let g = if offset[d] > 0 {
+                        T::of(offset[d] as f64) - frac[d]
+                    } else if offset[d] < 0 {
+                        T::of((-offset[d] - 1) as f64) + frac[d]
+                    } else {
+                        zero
+                    };
+                    gap = gap + g * g;
+                }
+                if near != *near_pass || gap >= f2 {
+                    continue;
+                }
+                let h = self.hash(&cell[..n]);
+                let mut dist = zero;
+                for d in 0..n {
+                    let delta = T::of(offset[d] as f64) + T::unit(h[d]) - frac[d];
+                    dist = dist + delta * delta;
+                }
+                if dist < f1 {
+                    f2 = f1;
+                    f1 = dist;
+                } else if dist < f2 {
+                    f2 = dist;
+                }
+            }
+        }
+        [f1.sqrt(), f2.sqrt()]
+    }
+
+    named!(value, T, value_2d(x, y); value_3d(x, y, z); value_4d(x, y, z, w););
+    named!(perlin, T, perlin_2d(x, y); perlin_3d(x, y, z); perlin_4d(x, y, z, w););
+    named!(simplex, T, simplex_2d(x, y); simplex_3d(x, y, z); simplex_4d(x, y, z, w););
+    named!(worley, [T; 2], worley_2d(x, y); worley_3d(x, y, z); worley_4d(x, y, z, w););
+}
+
+/// Sums of octaves of a noise function, each `lacunarity` times the
+/// frequency and `gain` times the amplitude of the one before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fractal {
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self { octaves: 6, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+impl Fractal {
+    fn sum<T: Float, F: Fn(&[T]) -> T>(&self, noise: F, p: &[T]) -> T {
+        check_dims(p.len());
+        let mut q = [T::of(0.0); 4];
+        let (mut frequency, mut amplitude) = (1.0, 1.0);
+        let (mut sum, mut total) = (T::of(0.0), 0.0);
+        for _ in 0..self.octaves {
+            for (q, &x) in q.iter_mut().zip(p) {
+                *q = x * T::of(frequency);
+            }
+            sum = sum + T::of(amplitude) * noise(&q[..p.len()]);
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        sum / T::of(total)
+    }
+
+    /// Fractional Brownian motion: the amplitude-weighted mean of the
+    /// octaves of `noise` at `p`.
+    pub fn fbm<T: Float, F: Fn(&[T]) -> T>(&self, noise: F, p: &[T]) -> T {
+        self.sum(noise, p)
+    }
+
+    /// Ridged multifractal: octaves of `(1 - |noise|)^2`, in `[0, 1]` for
+    /// noise in `[-1, 1]`.
+    pub fn ridged<T: Float, F: Fn(&[T]) -> T>(&self, noise: F, p: &[T]) -> T {
+        self.sum(|q| {
+            let v = noise(q);
+            let r = T::of(1.0) - if v < T::of(0.0) { -v } else { v };
+            r * r
+        }, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Noise, Fractal};
+    use philox::Philox4x64;
+    use rng::Philox4x64Rng;
+    use rand_core::RngCore;
+    use u01::u01_f64;
+
+    fn points(n: usize, dims: usize, scale: f64) -> Vec<Vec<f64>> {
+        let mut rng = Philox4x64Rng::from(Philox4x64::from_seed([4; 16]));
+        (0..n).map(|_| (0..dims).map(|_| scale * (2.0 * u01_f64(rng.next_u64()) - 1.0)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn lattice_values() {
+        let noise = Noise::new(17);
+        for dims in 2..5 {
+            let p = [3.0, -2.0, 5.0, 7.0];
+            // gradient noise vanishes on the lattice, value noise is exact
+            assert_eq!(noise.perlin(&p[..dims]), 0.0);
+            let v = noise.value(&p[..dims]);
+            assert!((-1.0..1.0).contains(&v));
+            let q: Vec<f64> = p[..dims].iter().map(|x| x + 1e-9).collect();
+            assert!((noise.value(&q) - v).abs() < 1e-6);
+        }
+        // the 3D lattice is the w = 0 slice of the 4D one
+        let a = noise.value_3d(0.3f64, 1.7, -4.2);
+        let b = noise.value_4d(0.3, 1.7, -4.2, 0.0);
+        assert!((a - b).abs() < 1e-15);
+        assert!(Noise::new(18).value_2d(0.5, 0.5) != noise.value_2d(0.5, 0.5));
+    }
+
+    #[test]
+    fn ranges_and_means() {
+        let noise = Noise::new(0x7e77a1);
+        for dims in 2..5 {
+            let ps = points(4000, dims, 50.0);
+            for (name, f, bound) in [("value", 0, 1.0), ("perlin", 1, 1.2), ("simplex", 2, 1.1)].iter() {
+                let values: Vec<f64> = ps.iter().map(|p| match *f {
+                    0 => noise.value(p),
+                    1 => noise.perlin(p),
+                    _ => noise.simplex(p),
+                }).collect();
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                let var = values.iter().map(|v| v * v).sum::<f64>() / values.len() as f64 - mean * mean;
+                let max = values.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+                assert!(max <= *bound, "{} {}: max {}", name, dims, max);
+                assert!(mean.abs() < 0.03, "{} {}: mean {}", name, dims, mean);
+                assert!(var > 0.01, "{} {}: variance {}", name, dims, var);
+            }
+        }
+    }
+
+    #[test]
+    fn continuity() {
+        let noise = Noise::new(3);
+        for p in points(500, 3, 20.0) {
+            let q = [p[0] + 1e-6, p[1] - 1e-6, p[2] + 1e-6];
+            assert!((noise.perlin(&p) - noise.perlin(&q)).abs() < 1e-4);
+            assert!((noise.simplex(&p) - noise.simplex(&q)).abs() < 1e-4);
+            assert!((noise.worley(&p)[0] - noise.worley(&q)[0]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn worley_nearest() {
+        let noise = Noise::new(11);
+        for dims in 2..5 {
+            for p in points(200, dims, 10.0) {
+                let [f1, f2] = noise.worley(&p);
+                assert!(f1 <= f2 && f1 <= (dims as f64).sqrt());
+                // brute force over a wider block of cells
+                let mut dists = vec![];
+                let cells = 9usize.pow(dims as u32);
+                for c in 0..cells {
+                    let mut cell = [0i32; 4];
+                    let mut rest = c;
+                    for d in 0..dims {
+                        cell[d] = p[d].floor() as i32 + (rest % 9) as i32 - 4;
+                        rest /= 9;
+                    }
+                    let h = noise.hash(&cell[..dims]);
+                    let dist: f64 = (0..dims).map(|d| {
+                        let x = cell[d] as f64 + h[d] as f64 / 4_294_967_296.0 - p[d];
+                        x * x
+                    }).sum();
+                    dists.push(dist);
+                }
+                dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert!((dists[0].sqrt() - f1).abs() < 1e-9);
+                assert!((dists[1].sqrt() - f2).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn single_precision() {
+        let noise = Noise::new(5);
+        for p in points(300, 4, 30.0) {
+            let q: Vec<f32> = p.iter().map(|&x| x as f32).collect();
+            let p: Vec<f64> = q.iter().map(|&x| x as f64).collect();
+            assert!((noise.value(&q) as f64 - noise.value(&p)).abs() < 1e-4);
+            assert!((noise.perlin(&q) as f64 - noise.perlin(&p)).abs() < 1e-4);
+            assert!((noise.simplex(&q) as f64 - noise.simplex(&p)).abs() < 1e-3);
+            assert!((noise.worley(&q)[0] as f64 - noise.worley(&p)[0]).abs() < 1e-4);
+        }
+        // far from the origin the lattice is still there
+        let v = noise.perlin_2d(1.0e6f32 + 0.5, -3.0e6 + 0.25);
+        assert!(v.abs() <= 1.2 && v != 0.0);
+    }
+
+    #[test]
+    fn fractal() {
+        let noise = Noise::new(8);
+        let one = Fractal { octaves: 1, ..Fractal::default() };
+        let p = [1.25, -0.75, 3.5];
+        assert_eq!(one.fbm(|q| noise.perlin(q), &p), noise.perlin(&p));
+        let fbm = Fractal::default();
+        for p in points(500, 2, 10.0) {
+            assert!(fbm.fbm(|q| noise.value(q), &p).abs() <= 1.0);
+            let r = fbm.ridged(|q| noise.simplex(q), &p);
+            assert!((0.0..=1.0).contains(&r));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "2 to 4 dimensions")]
+    fn one_dimension() {
+        Noise::new(0).value(&[0.5]);
+    }
+
+    #[test]
+    fn golden_values() {
+        let noise = Noise::new(2024);
+        let v = [noise.value_2d(0.5, 1.25), noise.perlin_3d(0.1, 0.2, 0.3),
+                 noise.simplex_4d(1.5, -2.5, 0.25, 9.0), noise.worley_2d(3.3, 4.4)[0]];
+        assert_eq!(v, [-0.7391094873096336, -0.21618221290690567,
+                       -0.3442254175684192, 0.7113136642887075]);
+        assert_eq!(noise.simplex_2d(0.7f32, 0.2), -0.5587444);
+    }
+}
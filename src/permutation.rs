@@ -0,0 +1,209 @@
+use super::philox::{philox_2x32, Array1x32};
+use super::threefry::{threefry_2x64, Array2x64};
+
+const ROUNDS: u32 = 8;
+
+/// Keyed function used for the rounds of the Feistel network; the round
+/// number and the right half form the counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundFunction {
+    Philox2x32(Array1x32),
+    ThreeFry2x64(Array2x64),
+}
+
+impl RoundFunction {
+    #[inline]
+    fn apply(&self, round: u32, half: u64) -> u64 {
+        match *self {
+            RoundFunction::Philox2x32(key) => philox_2x32([half as u32, round], key)[0] as u64,
+            RoundFunction::ThreeFry2x64(key) => {
+                let mut x = [0u64; 2];
+                threefry_2x64([half, round as u64], key, &mut x);
+                x[0]
+            }
+        }
+    }
+}
+
+/// A keyed bijection of `[0, n)`, computed without storing it.
+///
+/// A balanced Feistel network permutes the smallest domain of `2 * half_bits`
+/// bits that covers `n`, and values that land outside `[0, n)` are
+/// encrypted again (cycle walking) until they fall inside.
+#[derive(Clone, Copy, Debug)]
+pub struct Permutation {
+    n: u64,
+    half_bits: u32,
+    round: RoundFunction,
+}
+
+impl Permutation {
+    pub fn new(n: u64, round: RoundFunction) -> Self {
+        let bits = 64 - n.saturating_sub(1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+        Self { n, half_bits, round }
+    }
+
+    pub fn philox_2x32(n: u64, key: Array1x32) -> Self {
+        Self::new(n, RoundFunction::Philox2x32(key))
+    }
+
+    pub fn threefry_2x64(n: u64, key: Array2x64) -> Self {
+        Self::new(n, RoundFunction::ThreeFry2x64(key))
+    }
+
+    pub fn len(&self) -> u64 {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Position `i` of the permuted sequence.
+    pub fn forward(&self, i: u64) -> u64 {
+        assert!(i < self.n, "index {} out of range for permutation of {}", i, self.n);
+        let mut x = self.encrypt(i);
+        while x >= self.n {
+            x = self.encrypt(x);
+        }
+        x
+    }
+
+    /// The index `i` with `forward(i) == j`.
+    pub fn inverse(&self, j: u64) -> u64 {
+        assert!(j < self.n, "value {} out of range for permutation of {}", j, self.n);
+        let mut x = self.decrypt(j);
+        while x >= self.n {
+            x = self.decrypt(x);
+        }
+        x
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { perm: self, next: 0 }
+    }
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.half_bits)
+    }
+
+    fn encrypt(&self, x: u64) -> u64 {
+        let mask = self.mask();
+        let (mut l, mut r) = (x >> self.half_bits, x & mask);
+        for round in 0..ROUNDS {
+            let f = self.round.apply(round, r) & mask;
+            let t = l ^ f;
+            l = r;
+            r = t;
+        }
+        l << self.half_bits | r
+    }
+
+    fn decrypt(&self, x: u64) -> u64 {
+        let mask = self.mask();
+        let (mut l, mut r) = (x >> self.half_bits, x & mask);
+        for round in (0..ROUNDS).rev() {
+            let f = self.round.apply(round, l) & mask;
+            let t = r ^ f;
+            r = l;
+            l = t;
+        }
+        l << self.half_bits | r
+    }
+}
+
+impl<'a> IntoIterator for &'a Permutation {
+    type Item = u64;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterates over `forward(0), forward(1), ...`.
+#[derive(Clone, Debug)]
+pub struct Iter<'a> {
+    perm: &'a Permutation,
+    next: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.next == self.perm.n {
+            return None;
+        }
+        let x = self.perm.forward(self.next);
+        self.next += 1;
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = (self.perm.n - self.next) as usize;
+        (left, Some(left))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::Permutation;
+
+    fn check_bijection(perm: &Permutation) {
+        let n = perm.len();
+        let mut seen = vec![false; n as usize];
+        for (i, j) in perm.iter().enumerate() {
+            assert!(!seen[j as usize]);
+            seen[j as usize] = true;
+            assert_eq!(perm.inverse(j), i as u64);
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn bijective_small_n() {
+        for n in 0..300 {
+            check_bijection(&Permutation::philox_2x32(n, [n as u32]));
+            check_bijection(&Permutation::threefry_2x64(n, [n, 17]));
+        }
+        check_bijection(&Permutation::philox_2x32(4096, [1]));
+        check_bijection(&Permutation::threefry_2x64(5000, [2, 3]));
+    }
+
+    #[test]
+    fn large_n_round_trip() {
+        for &n in [(1u64 << 40) + 3, u64::MAX, 1 << 63].iter() {
+            let perm = Permutation::philox_2x32(n, [99]);
+            let perm2 = Permutation::threefry_2x64(n, [99, 100]);
+            for i in 0..200 {
+                let i = i * (n / 200);
+                assert!(perm.forward(i) < n);
+                assert_eq!(perm.inverse(perm.forward(i)), i);
+                assert_eq!(perm2.inverse(perm2.forward(i)), i);
+            }
+        }
+    }
+
+    #[test]
+    fn keys_and_epochs() {
+        let epoch0 = Permutation::philox_2x32(1000, [0]);
+        let epoch1 = Permutation::philox_2x32(1000, [1]);
+        let a: Vec<u64> = epoch0.iter().collect();
+        let b: Vec<u64> = epoch1.iter().collect();
+        assert!(a != b);
+        assert_eq!(a, Permutation::philox_2x32(1000, [0]).iter().collect::<Vec<_>>());
+        // not the identity, and not close to it
+        let fixed = a.iter().enumerate().filter(|&(i, &j)| i as u64 == j).count();
+        assert!(fixed < 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn forward_out_of_range() {
+        Permutation::threefry_2x64(10, [0, 0]).forward(10);
+    }
+}
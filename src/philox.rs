@@ -1,5 +1,7 @@
 use rand_core::le;
 
+use super::counter::CounterState;
+
 #[inline]
 fn mul32(a: u32, b:u32) -> (u32, u32) {
     let prod = (a as u64).wrapping_mul(b as u64);
@@ -35,105 +37,89 @@ pub type Array4x64 = [u64; 4];
 
 #[derive(Clone)]
 pub struct Philox2x32 {
-    ctr: Array2x32,
+    ctr: CounterState<Array2x32>,
     key: Array1x32,
 }
 
 impl Philox2x32 {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Array2x32 {
-        let results = philox_2x32(self.ctr, self.key);
-        self.ctr[0] = self.ctr[0].wrapping_add(1);
-        if self.ctr[0] == 0 {
-            self.ctr[1] = self.ctr[1].wrapping_add(1);
-        }
-        results
+        philox_2x32(self.ctr.take(), self.key)
     }
     pub fn from_seed(seed: [u8; 4]) -> Self {
         let mut key = [0u32; 1];
         le::read_u32_into(&seed, &mut key);
-        Self { ctr: [0,0], key: key }
+        Self { ctr: CounterState::new([0,0]), key }
     }
 }
 
+impl_counter_access!(Philox2x32, Array2x32);
+impl_word_at!(Philox2x32, Array1x32, u32, 2, philox_2x32);
+
 #[derive(Clone)]
 pub struct Philox2x64 {
-    ctr: Array2x64,
+    ctr: CounterState<Array2x64>,
     key: Array1x64,
 }
 
 impl Philox2x64 {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Array2x64 {
-        let results = philox_2x64(self.ctr, self.key);
-        self.ctr[0] = self.ctr[0].wrapping_add(1);
-        if self.ctr[0] == 0 {
-            self.ctr[1] = self.ctr[1].wrapping_add(1);
-        }
-        results
+        philox_2x64(self.ctr.take(), self.key)
     }
     pub fn from_seed(seed: [u8; 8]) -> Self {
         let mut key = [0u64; 1];
         le::read_u64_into(&seed, &mut key);
-        Self { ctr: [0,0], key: key }
+        Self { ctr: CounterState::new([0,0]), key }
     }
 }
 
+impl_counter_access!(Philox2x64, Array2x64);
+impl_word_at!(Philox2x64, Array1x64, u64, 2, philox_2x64);
+
 #[derive(Clone)]
 pub struct Philox4x32 {
-    ctr: Array4x32,
+    ctr: CounterState<Array4x32>,
     key: Array2x32,
 }
 
 impl Philox4x32 {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Array4x32 {
-        let results = philox_4x32(self.ctr, self.key);
-        self.ctr[0] = self.ctr[0].wrapping_add(1);
-        if self.ctr[0] == 0 {
-            self.ctr[1] = self.ctr[1].wrapping_add(1);
-            if self.ctr[1] == 0 {
-                self.ctr[2] = self.ctr[2].wrapping_add(1);
-                if self.ctr[2] == 0 {
-                    self.ctr[3] = self.ctr[3].wrapping_add(1);
-                }
-            }
-        }
-        results
+        philox_4x32(self.ctr.take(), self.key)
     }
     pub fn from_seed(seed: [u8; 8]) -> Self {
         let mut key = [0u32; 2];
         le::read_u32_into(&seed, &mut key);
-        Self { ctr: [0,0,0,0], key: key }
+        Self { ctr: CounterState::new([0,0,0,0]), key }
     }
 }
 
+impl_counter_access!(Philox4x32, Array4x32);
+impl_word_at!(Philox4x32, Array2x32, u32, 4, philox_4x32);
+
 #[derive(Clone)]
 pub struct Philox4x64 {
-    ctr: Array4x64,
+    ctr: CounterState<Array4x64>,
     key: Array2x64,
 }
 
 impl Philox4x64 {
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Array4x64 {
-        let results = philox_4x64(self.ctr, self.key);
-        self.ctr[0] = self.ctr[0].wrapping_add(1);
-        if self.ctr[0] == 0 {
-            self.ctr[1] = self.ctr[1].wrapping_add(1);
-            if self.ctr[1] == 0 {
-                self.ctr[2] = self.ctr[2].wrapping_add(1);
-                if self.ctr[2] == 0 {
-                    self.ctr[3] = self.ctr[3].wrapping_add(1);
-                }
-            }
-        }
-        results
+        philox_4x64(self.ctr.take(), self.key)
     }
     pub fn from_seed(seed: [u8; 16]) -> Self {
         let mut key = [0u64; 2];
         le::read_u64_into(&seed, &mut key);
-        Self { ctr: [0,0,0,0], key: key }
+        Self { ctr: CounterState::new([0,0,0,0]), key }
     }
 }
 
+impl_counter_access!(Philox4x64, Array4x64);
+impl_word_at!(Philox4x64, Array2x64, u64, 4, philox_4x64);
+
 #[inline]
 fn philox_2x32round(ctr: Array2x32, key: Array1x32) -> Array2x32 {
     let (hi, lo) = mul32(PHILOX_M2X32_0, ctr[0]);
@@ -299,6 +285,7 @@ mod tests {
     use super::{philox_2x32, philox_4x32, philox_2x64, philox_4x64};
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn exact_values_philox_2x32() {
         let mut ctr: Array2x32 = [0,0];
         let key: Array1x32 = [SEED1];
@@ -313,6 +300,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn exact_values_philox_2x64() {
         let mut ctr: Array2x64 = [0,0];
         let key: Array1x64 = [SEED1 as u64];
@@ -327,6 +315,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn exact_values_philox_4x32() {
         let mut ctr: Array4x32 = [0,0,0,0];
         let key: Array2x32 = [SEED1, SEED2];
@@ -345,6 +334,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn exact_values_philox_4x64() {
         let mut ctr: Array4x64 = [0,0,0,0];
         let key: Array2x64 = [SEED1 as u64, SEED2 as u64];
@@ -1,12 +1,17 @@
 use core::fmt;
-use rand_core::{RngCore, SeedableRng, Error};
+use core::mem;
+use rand_core::{impls, RngCore, SeedableRng, Error, ErrorKind};
 use rand_core::block::{BlockRngCore, BlockRng, BlockRng64};
 
 use super::philox::{Philox2x32,  Philox2x64,  Philox4x32,  Philox4x64};
+use super::philox::{philox_4x32, Array2x32, Array4x32};
 use super::threefry::{ThreeFry2x64};
+use super::counter::WrapPolicy;
+use super::skein::skein_256;
+use super::ints::lemire_u128;
 
 macro_rules! impl_rng {
-    ($t: ty, $n:expr, $i: ty, $b: expr, $block: ident, $rng: path) => {
+    ($t: ty, $n:expr, $i: ty, $b: expr, $block: ident, $rng: path, $next: ident) => {
 
         impl BlockRngCore for $t {
             type Item = $i;
@@ -25,6 +30,16 @@ macro_rules! impl_rng {
             }
         }
 
+        impl $t {
+            /// Seeds the core from key material of any length by hashing it
+            /// with Skein-256, using the seed size as output length.
+            pub fn from_key_material(material: &[u8]) -> Self {
+                let mut seed = [0u8; $b];
+                skein_256(material, &mut seed);
+                <$t>::from_seed(seed)
+            }
+        }
+
         impl fmt::Debug for $t {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 write!(f, "{} {{}}", stringify!($t))
@@ -43,6 +58,79 @@ macro_rules! impl_rng {
             }
         }
 
+        impl $rng {
+            pub fn from_key_material(material: &[u8]) -> Self {
+                Self::from(<$t>::from_key_material(material))
+            }
+
+            /// Counter of the next block the core will generate; words
+            /// already buffered come from earlier counters.
+            pub fn counter(&self) -> [$i; $n] {
+                self.0.core.counter()
+            }
+
+            /// Moves the stream to `ctr`, discarding any buffered words.
+            pub fn set_counter(&mut self, ctr: [$i; $n]) {
+                self.0.core.set_counter(ctr);
+                self.0.reset();
+            }
+
+            pub fn counter_limit(&self) -> Option<[$i; $n]> {
+                self.0.core.counter_limit()
+            }
+
+            pub fn set_counter_limit(&mut self, limit: Option<[$i; $n]>) {
+                self.0.core.set_counter_limit(limit)
+            }
+
+            pub fn wrap_policy(&self) -> WrapPolicy {
+                self.0.core.wrap_policy()
+            }
+
+            pub fn set_wrap_policy(&mut self, policy: WrapPolicy) {
+                self.0.core.set_wrap_policy(policy)
+            }
+
+            pub fn blocks_remaining(&self) -> u128 {
+                self.0.core.blocks_remaining()
+            }
+
+            /// The next 128 bits that start on a 128-bit boundary of a
+            /// block, low word first: one whole block of Philox4x32,
+            /// Philox2x64 or ThreeFry2x64, two blocks of Philox2x32 and half
+            /// a block of Philox4x64. Buffered words short of the boundary
+            /// are discarded.
+            pub fn next_u128(&mut self) -> u128 {
+                const WORDS: usize = 16 / mem::size_of::<$i>();
+                let step = if WORDS < $n { WORDS } else { $n };
+                let index = self.0.index();
+                if index % step != 0 {
+                    let aligned = index.div_ceil(step) * step;
+                    if aligned >= $n {
+                        self.0.reset();
+                    } else {
+                        for _ in index..aligned {
+                            self.0.$next();
+                        }
+                    }
+                }
+                let mut x = 0u128;
+                for w in 0..WORDS {
+                    x |= (self.0.$next() as u128) << (w * 8 * mem::size_of::<$i>());
+                }
+                x
+            }
+
+            /// Uniform below `bound` by Lemire's method on `next_u128`, so
+            /// each draw takes one 128-bit word, plus one per rejection.
+            /// Unlike `ints::below_u128`, which joins two `next_u64` from
+            /// wherever the generator stands, this skips to the next 128-bit
+            /// boundary first, so the two differ after an odd-sized draw.
+            pub fn below_u128(&mut self, bound: u128) -> u128 {
+                lemire_u128(|| self.next_u128(), bound)
+            }
+        }
+
         impl From<$t> for $rng {
             fn from(core: $t) -> Self {
                 $rng($block::new(core))
@@ -67,6 +155,14 @@ macro_rules! impl_rng {
         
             #[inline]
             fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+                if self.0.core.wrap_policy() == WrapPolicy::Error {
+                    let words = dest.len().div_ceil(mem::size_of::<$i>());
+                    let buffered = $n - self.0.index();
+                    let blocks = words.saturating_sub(buffered).div_ceil($n);
+                    if blocks as u128 > self.0.core.blocks_remaining() {
+                        return Err(Error::new(ErrorKind::Unavailable, "counter range exhausted"));
+                    }
+                }
                 self.0.try_fill_bytes(dest)
             }
         }
@@ -84,17 +180,67 @@ pub struct Philox4x64Rng(BlockRng64<Philox4x64>);
 #[derive(Clone, Debug)]
 pub struct ThreeFry2x64Rng(BlockRng64<ThreeFry2x64>);
 
-impl_rng!(Philox2x32, 2, u32, 4, BlockRng,   Philox2x32Rng);
-impl_rng!(Philox2x64, 2, u64, 8, BlockRng64, Philox2x64Rng);
-impl_rng!(Philox4x32, 4, u32, 8, BlockRng,   Philox4x32Rng);
-impl_rng!(Philox4x64, 4, u64,16, BlockRng64, Philox4x64Rng);
+impl_rng!(Philox2x32, 2, u32, 4, BlockRng,   Philox2x32Rng, next_u32);
+impl_rng!(Philox2x64, 2, u64, 8, BlockRng64, Philox2x64Rng, next_u64);
+impl_rng!(Philox4x32, 4, u32, 8, BlockRng,   Philox4x32Rng, next_u32);
+impl_rng!(Philox4x64, 4, u64,16, BlockRng64, Philox4x64Rng, next_u64);
+
+impl_rng!(ThreeFry2x64, 2, u64,16, BlockRng64, ThreeFry2x64Rng, next_u64);
+
+/// Words of `philox_4x32(ctr, key)` in lane order, for values that must
+/// depend only on `(key, ctr)`. If more words are needed the block is
+/// encrypted again under the same key, so unlike a stream positioned at
+/// `ctr` this never reaches the block of a neighbouring counter.
+///
+/// `next_u64` joins two lanes, low lane first.
+#[derive(Clone, Debug)]
+pub struct CounterRng {
+    key: Array2x32,
+    block: Array4x32,
+    index: usize,
+}
+
+impl CounterRng {
+    pub fn new(key: Array2x32, ctr: Array4x32) -> Self {
+        Self { key, block: philox_4x32(ctr, key), index: 0 }
+    }
+}
+
+impl RngCore for CounterRng {
+    fn next_u32(&mut self) -> u32 {
+        if self.index == self.block.len() {
+            self.block = philox_4x32(self.block, self.key);
+            self.index = 0;
+        }
+        self.index += 1;
+        self.block[self.index - 1]
+    }
 
-impl_rng!(ThreeFry2x64, 2, u64,16, BlockRng64, ThreeFry2x64Rng);
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod tests {
 
-    use super::ThreeFry2x64Rng;
+    use super::{Philox2x32Rng, Philox2x64Rng, Philox4x32Rng, Philox4x64Rng, ThreeFry2x64Rng};
+    use super::CounterRng;
+    use counter::{Counter, WrapPolicy};
+    use philox::{Philox2x32, Philox2x64, Philox4x32, Philox4x64, philox_4x32};
+    use threefry::ThreeFry2x64;
+    use ints;
+    use skein::skein_256;
+    use rand_core::le;
     use rand_core::{SeedableRng, RngCore};
 
     #[test]
@@ -105,5 +251,249 @@ mod tests {
         let mut rng = ThreeFry2x64Rng::seed_from_u64(42);
         rng.fill_bytes(&mut buf);
     }
-}
 
+    #[test]
+    fn wrap_by_default() {
+        let mut rng = Philox2x32Rng::seed_from_u64(7);
+        let first = rng.next_u32();
+        rng.set_counter([u32::MAX, u32::MAX]);
+        rng.next_u32();
+        rng.next_u32();
+        assert_eq!(rng.next_u32(), first);
+    }
+
+    #[test]
+    fn wrap_within_limit() {
+        let mut rng = Philox4x64Rng::seed_from_u64(7);
+        rng.set_counter([5, 0, 0, 9]);
+        rng.set_counter_limit(Some([7, 0, 0, 9]));
+        let first: Vec<u64> = (0..8).map(|_| rng.next_u64()).collect();
+        let again: Vec<u64> = (0..8).map(|_| rng.next_u64()).collect();
+        assert_eq!(first, again);
+        assert_eq!(rng.counter(), [7, 0, 0, 9]);
+    }
+
+    #[test]
+    #[should_panic(expected = "counter range exhausted")]
+    fn panic_policy() {
+        let mut rng = Philox2x32Rng::seed_from_u64(7);
+        rng.set_wrap_policy(WrapPolicy::Panic);
+        rng.set_counter([u32::MAX - 1, u32::MAX]);
+        for _ in 0..5 {
+            rng.next_u32();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "counter range is empty")]
+    fn empty_range() {
+        let mut rng = Philox2x32Rng::seed_from_u64(7);
+        rng.set_counter([5, 0]);
+        rng.set_counter_limit(Some([3, 0]));
+        rng.set_wrap_policy(WrapPolicy::Error);
+        assert!(rng.try_fill_bytes(&mut [0u8; 4]).is_err());
+        rng.set_wrap_policy(WrapPolicy::Wrap);
+        rng.next_u32();
+    }
+
+    #[test]
+    fn error_policy() {
+        let mut rng = Philox2x32Rng::seed_from_u64(7);
+        rng.set_wrap_policy(WrapPolicy::Error);
+        rng.set_counter([0, 3]);
+        rng.set_counter_limit(Some([4, 3]));
+        assert_eq!(rng.blocks_remaining(), 4);
+
+        let mut buf = [0u8; 32];
+        assert!(rng.try_fill_bytes(&mut buf[..5]).is_ok());
+        assert!(rng.try_fill_bytes(&mut buf[..25]).is_err());
+        assert!(rng.try_fill_bytes(&mut buf[..24]).is_ok());
+        assert_eq!(rng.blocks_remaining(), 0);
+        assert!(rng.try_fill_bytes(&mut buf[..1]).is_err());
+        assert_eq!(rng.counter(), [4, 3]);
+    }
+
+    macro_rules! word_at_test {
+        ($name: ident, $t: ident, $rng: ident, $w: ty, $kn: expr, $n: expr, $read: ident) => {
+            #[test]
+            fn $name() {
+                let mut seed = [0u8; $kn * ::std::mem::size_of::<$w>()];
+                for (i, b) in seed.iter_mut().enumerate() {
+                    *b = 0x5a ^ (i as u8).wrapping_mul(37);
+                }
+                let mut key = [0 as $w; $kn];
+                le::$read(&seed, &mut key);
+
+                // BlockRng64::next_u32 in rand_core 0.4 indexes past the end
+                // of its u32 view after the first few calls, so only check the
+                // split of 64-bit words within that range.
+                let halves = if ::std::mem::size_of::<$w>() == 4 { 40 } else { $n };
+                let mut rng = $rng::from($t::from_seed(seed));
+                for i in 0..halves {
+                    assert_eq!(rng.next_u32(), $t::u32_at(key, i));
+                }
+                let mut rng = $rng::from($t::from_seed(seed));
+                for i in 0..40 {
+                    let word = if ::std::mem::size_of::<$w>() == 4 {
+                        rng.next_u32() as u64
+                    } else {
+                        rng.next_u64()
+                    };
+                    assert_eq!(word, $t::word_at(key, i) as u64);
+                }
+
+                let start = [<$w>::MAX - 1, 3, 0, 0];
+                let mut ctr = [0 as $w; $n];
+                ctr.copy_from_slice(&start[..$n]);
+                let base = ctr.to_u256();
+                let base = (base[0] as u128 | (base[1] as u128) << 64).wrapping_mul($n);
+                let mut rng = $rng::from($t::from_seed(seed));
+                rng.set_counter(ctr);
+                for i in 0..5 * $n {
+                    let word = if ::std::mem::size_of::<$w>() == 4 {
+                        rng.next_u32() as u64
+                    } else {
+                        rng.next_u64()
+                    };
+                    assert_eq!(word, $t::word_at(key, base + i) as u64);
+                }
+            }
+        }
+    }
+
+    word_at_test!(word_at_philox_2x32, Philox2x32, Philox2x32Rng, u32, 1, 2, read_u32_into);
+    word_at_test!(word_at_philox_2x64, Philox2x64, Philox2x64Rng, u64, 1, 2, read_u64_into);
+    word_at_test!(word_at_philox_4x32, Philox4x32, Philox4x32Rng, u32, 2, 4, read_u32_into);
+    word_at_test!(word_at_philox_4x64, Philox4x64, Philox4x64Rng, u64, 2, 4, read_u64_into);
+    word_at_test!(word_at_threefry_2x64, ThreeFry2x64, ThreeFry2x64Rng, u64, 2, 2, read_u64_into);
+
+    macro_rules! next_u128_test {
+        ($name: ident, $t: ident, $rng: ident, $w: ty, $kn: expr, $n: expr, $read: ident, $next: ident) => {
+            #[test]
+            fn $name() {
+                let mut seed = [0u8; $kn * ::std::mem::size_of::<$w>()];
+                for (i, b) in seed.iter_mut().enumerate() {
+                    *b = 0xc3 ^ (i as u8).wrapping_mul(11);
+                }
+                let mut key = [0 as $w; $kn];
+                le::$read(&seed, &mut key);
+
+                let words = 16 / ::std::mem::size_of::<$w>();
+                let step = words.min($n);
+                let expect = |start: usize| (0..words).fold(0u128, |x, w| {
+                    x | ($t::word_at(key, (start + w) as u128) as u128) << (w * 128 / words)
+                });
+                let mut rng = $rng::from($t::from_seed(seed));
+                assert_eq!(rng.next_u128(), expect(0));
+                assert_eq!(rng.next_u128(), expect(words));
+                // a single word leaves the rest of its 128-bit unit unused
+                rng.$next();
+                let start = (2 * words + 1).div_ceil(step) * step;
+                assert_eq!(rng.next_u128(), expect(start));
+                assert_eq!(rng.$next() as u64, $t::word_at(key, (start + words) as u128) as u64);
+            }
+        }
+    }
+
+    next_u128_test!(next_u128_philox_2x32, Philox2x32, Philox2x32Rng, u32, 1, 2, read_u32_into, next_u32);
+    next_u128_test!(next_u128_philox_2x64, Philox2x64, Philox2x64Rng, u64, 1, 2, read_u64_into, next_u64);
+    next_u128_test!(next_u128_philox_4x32, Philox4x32, Philox4x32Rng, u32, 2, 4, read_u32_into, next_u32);
+    next_u128_test!(next_u128_philox_4x64, Philox4x64, Philox4x64Rng, u64, 2, 4, read_u64_into, next_u64);
+    next_u128_test!(next_u128_threefry_2x64, ThreeFry2x64, ThreeFry2x64Rng, u64, 2, 2, read_u64_into, next_u64);
+
+    #[test]
+    fn below_u128() {
+        // a plain modulo of 2^128 would put half the values below 2^126
+        // instead of a third
+        let bound = 3u128 << 126;
+        let mut rng = Philox4x64Rng::seed_from_u64(5);
+        let trials = 30000;
+        let low = (0..trials).filter(|_| rng.below_u128(bound) < 1 << 126).count();
+        let p = low as f64 / trials as f64;
+        assert!((p - 1.0 / 3.0).abs() < 0.015, "{}", p);
+
+        let mut rng = Philox4x32Rng::seed_from_u64(5);
+        for b in 1..200u128 {
+            assert!(rng.below_u128(b << 100) < b << 100);
+        }
+        assert_eq!(rng.below_u128(1), 0);
+        // power-of-two bounds never reject, so each draw is one block
+        let mut other = rng.clone();
+        assert_eq!(rng.below_u128(1 << 127), other.next_u128() >> 1);
+
+        // after one 32-bit word, ints::below_u128 starts at the next word and
+        // this one at the next block
+        let key = [11, 0];
+        let word = |i: u128| Philox4x32::word_at(key, i) as u128;
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([11, 0, 0, 0, 0, 0, 0, 0]));
+        let mut other = rng.clone();
+        rng.next_u32();
+        other.next_u32();
+        let aligned = word(4) | word(5) << 32 | word(6) << 64 | word(7) << 96;
+        let unaligned = word(1) | word(2) << 32 | word(3) << 64 | word(4) << 96;
+        assert_eq!(rng.below_u128(1 << 127), aligned >> 1);
+        assert_eq!(ints::below_u128(&mut other, 1 << 127), unaligned >> 1);
+    }
+
+    #[test]
+    fn golden_u128() {
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([11, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(rng.next_u128(), 113909924728025058527796766157943563878);
+        let mut rng = ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([11; 16]));
+        assert_eq!(rng.below_u128(10u128.pow(30)), 788241521770380462005191855078);
+    }
+
+    #[test]
+    fn u64_at() {
+        let mut rng = Philox4x32Rng::from(Philox4x32::from_seed([11, 0, 0, 0, 0, 0, 0, 0]));
+        let key = [11, 0];
+        for i in 0..20 {
+            assert_eq!(rng.next_u64(), Philox4x32::u64_at(key, i));
+        }
+        // BlockRng::next_u64 cannot handle two-word blocks, so compare
+        // Philox2x32 against pairs of 32-bit words instead.
+        let mut rng = Philox2x32Rng::from(Philox2x32::from_seed([11, 0, 0, 0]));
+        let key = [11];
+        for i in 0..20 {
+            let lo = rng.next_u32() as u64;
+            let hi = rng.next_u32() as u64;
+            assert_eq!(lo | hi << 32, Philox2x32::u64_at(key, i));
+        }
+        let mut rng = ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([11; 16]));
+        let key = [0x0b0b0b0b0b0b0b0b; 2];
+        rng.next_u32();
+        assert_eq!(rng.next_u64(), ThreeFry2x64::u64_at(key, 1));
+        assert_eq!(ThreeFry2x64::u32_at(key, 5), (ThreeFry2x64::word_at(key, 2) >> 32) as u32);
+    }
+
+    #[test]
+    fn from_key_material() {
+        let mut seed = [0u8; 16];
+        skein_256(b"experiment 42, replicate 7", &mut seed);
+        let mut a = ThreeFry2x64Rng::from_key_material(b"experiment 42, replicate 7");
+        let mut b = ThreeFry2x64Rng::from_seed(seed);
+        assert_eq!(a.next_u64(), b.next_u64());
+
+        let mut seed = [0u8; 4];
+        skein_256(b"", &mut seed);
+        let mut a = Philox2x32Rng::from_key_material(b"");
+        let mut b = Philox2x32Rng::from(Philox2x32::from_seed(seed));
+        assert_eq!(a.next_u32(), b.next_u32());
+
+        let mut a = Philox4x32Rng::from_key_material(b"run 1");
+        let mut b = Philox4x32Rng::from_key_material(b"run 2");
+        assert!(a.next_u64() != b.next_u64());
+    }
+
+    #[test]
+    fn counter_rng() {
+        let key = [5, 6];
+        let block = philox_4x32([1, 2, 3, 4], key);
+        let mut rng = CounterRng::new(key, [1, 2, 3, 4]);
+        assert_eq!(rng.next_u64(), block[0] as u64 | (block[1] as u64) << 32);
+        assert_eq!(rng.next_u32(), block[2]);
+        assert_eq!(rng.next_u32(), block[3]);
+        assert_eq!(rng.next_u32(), philox_4x32(block, key)[0]);
+        assert!(CounterRng::new(key, [2, 2, 3, 4]).next_u32() != block[0]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Sampling algorithms whose output for a given generator state is fixed by
+//! this crate, independent of the `rand` version in use.
+//!
+//! Word counts below are in `next_u64` draws; a bounded draw uses one word
+//! plus one more for each (rare) rejection, and a uniform float uses one.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+use rand_core::RngCore;
+
+use super::ints::below_u64;
+use super::u01::u01_open_f64;
+
+/// `k` distinct values from `[0, n)` by Floyd's algorithm, in the order
+/// they were chosen. Uses `k` bounded draws.
+pub fn sample_floyd<R: RngCore + ?Sized>(rng: &mut R, n: u64, k: u64) -> Vec<u64> {
+    assert!(k <= n, "cannot sample {} distinct values from {}", k, n);
+    let mut chosen = Vec::with_capacity(k as usize);
+    let mut set = HashSet::with_capacity(k as usize);
+    for j in n - k..n {
+        let t = below_u64(rng, j + 1);
+        let x = if set.contains(&t) { j } else { t };
+        set.insert(x);
+        chosen.push(x);
+    }
+    chosen
+}
+
+/// Uniform sample of `k` items from `iter` by reservoir sampling
+/// (Li's Algorithm L). Items are returned in reservoir order.
+///
+/// Uses one uniform to start, then per replacement two uniforms and one
+/// bounded draw; items that are skipped consume nothing.
+pub fn reservoir<R, I>(rng: &mut R, iter: I, k: usize) -> Vec<I::Item>
+    where R: RngCore + ?Sized, I: IntoIterator
+{
+    let mut iter = iter.into_iter();
+    let mut sample: Vec<I::Item> = iter.by_ref().take(k).collect();
+    if k == 0 || sample.len() < k {
+        return sample;
+    }
+    let k_inv = 1.0 / k as f64;
+    let mut w = (u01_open_f64(rng.next_u64()).ln() * k_inv).exp();
+    loop {
+        let skip = (u01_open_f64(rng.next_u64()).ln() / (-w).ln_1p()).floor();
+        let item = match iter.nth(skip as usize) {
+            Some(item) => item,
+            None => break,
+        };
+        sample[below_u64(rng, k as u64) as usize] = item;
+        w *= (u01_open_f64(rng.next_u64()).ln() * k_inv).exp();
+    }
+    sample
+}
+
+struct Keyed<T> {
+    key: f64,
+    item: T,
+}
+
+impl<T> PartialEq for Keyed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for Keyed<T> {}
+
+impl<T> PartialOrd for Keyed<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Keyed<T> {
+    // reversed, so that the heap keeps the smallest key on top
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.partial_cmp(&self.key).unwrap()
+    }
+}
+
+/// Weighted sample of `k` items without replacement from `(item, weight)`
+/// pairs, by Efraimidis and Spirakis' exponential-jump reservoir (A-ExpJ).
+/// Items are returned from the highest key to the lowest, which is a
+/// weighted random order. Items with zero weight are never chosen.
+///
+/// Uses one uniform per item while the reservoir fills, then two uniforms
+/// per replacement plus one at the end of the fill.
+pub fn weighted_reservoir<R, I, T>(rng: &mut R, iter: I, k: usize) -> Vec<T>
+    where R: RngCore + ?Sized, I: IntoIterator<Item = (T, f64)>
+{
+    // Keys are ln(u) / w, the log of the usual u^(1/w).
+    let mut heap = BinaryHeap::with_capacity(k);
+    let mut jump = 0.0;
+    if k > 0 {
+        for (item, weight) in iter {
+            assert!(weight >= 0.0, "weights must be non-negative, got {}", weight);
+            if weight == 0.0 {
+                continue;
+            }
+            if heap.len() < k {
+                let key = u01_open_f64(rng.next_u64()).ln() / weight;
+                heap.push(Keyed { key, item });
+                if heap.len() == k {
+                    jump = u01_open_f64(rng.next_u64()).ln() / heap.peek().unwrap().key;
+                }
+                continue;
+            }
+            jump -= weight;
+            if jump > 0.0 {
+                continue;
+            }
+            let threshold = heap.peek().unwrap().key;
+            // u uniform on (exp(threshold * weight), 1) has key above threshold
+            let low = (threshold * weight).exp();
+            let u = low + (1.0 - low) * u01_open_f64(rng.next_u64());
+            heap.pop();
+            heap.push(Keyed { key: u.ln() / weight, item });
+            jump = u01_open_f64(rng.next_u64()).ln() / heap.peek().unwrap().key;
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|k| k.item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_floyd, reservoir, weighted_reservoir};
+    use philox::Philox4x32;
+    use threefry::ThreeFry2x64;
+    use rng::{Philox4x32Rng, ThreeFry2x64Rng};
+
+    fn philox() -> Philox4x32Rng {
+        Philox4x32Rng::from(Philox4x32::from_seed([1, 2, 3, 4, 5, 6, 7, 8]))
+    }
+
+    fn threefry() -> ThreeFry2x64Rng {
+        ThreeFry2x64Rng::from(ThreeFry2x64::from_seed([9; 16]))
+    }
+
+    #[test]
+    fn floyd_distinct() {
+        let mut rng = philox();
+        let s = sample_floyd(&mut rng, 50, 50);
+        let mut sorted = s.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+        assert!(sample_floyd(&mut rng, 10, 0).is_empty());
+        let s = sample_floyd(&mut rng, 1 << 40, 1000);
+        assert!(s.iter().all(|&x| x < 1 << 40));
+    }
+
+    #[test]
+    fn floyd_golden() {
+        assert_eq!(sample_floyd(&mut philox(), 1000, 8), GOLDEN_FLOYD_PHILOX);
+        assert_eq!(sample_floyd(&mut threefry(), 1000, 8), GOLDEN_FLOYD_THREEFRY);
+    }
+
+    #[test]
+    fn reservoir_uniform() {
+        // each of 20 items should land in a 5-item sample a quarter of the time
+        let mut rng = threefry();
+        let mut counts = [0u32; 20];
+        let trials = 20000;
+        for _ in 0..trials {
+            for x in reservoir(&mut rng, 0..20, 5) {
+                counts[x] += 1;
+            }
+        }
+        for &c in counts.iter() {
+            let p = c as f64 / trials as f64;
+            assert!((p - 0.25).abs() < 0.02, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn reservoir_edges() {
+        let mut rng = philox();
+        assert!(reservoir(&mut rng, 0..100, 0).is_empty());
+        assert_eq!(reservoir(&mut rng, 0..3, 5), vec![0, 1, 2]);
+        let mut s = reservoir(&mut rng, 0..5, 5);
+        s.sort();
+        assert_eq!(s, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reservoir_golden() {
+        assert_eq!(reservoir(&mut philox(), 0..1_000_000u64, 6), GOLDEN_RESERVOIR_PHILOX);
+        assert_eq!(reservoir(&mut threefry(), 0..1_000_000u64, 6), GOLDEN_RESERVOIR_THREEFRY);
+    }
+
+    #[test]
+    fn weighted_frequencies() {
+        // with k = 1 the item is chosen with probability proportional to weight
+        let weights = [1.0, 2.0, 3.0, 0.0, 4.0];
+        let mut rng = philox();
+        let mut counts = [0u32; 5];
+        let trials = 20000;
+        for _ in 0..trials {
+            let items = weights.iter().cloned().enumerate();
+            for i in weighted_reservoir(&mut rng, items, 1) {
+                counts[i] += 1;
+            }
+        }
+        assert_eq!(counts[3], 0);
+        for (i, &w) in weights.iter().enumerate() {
+            let p = counts[i] as f64 / trials as f64;
+            assert!((p - w / 10.0).abs() < 0.015, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn weighted_edges() {
+        let mut rng = philox();
+        let items = (0..10).map(|i| (i, 1.0));
+        assert!(weighted_reservoir(&mut rng, items, 0).is_empty());
+        let items = vec![("a", 1.0), ("b", 0.0), ("c", 5.0)];
+        let mut s = weighted_reservoir(&mut rng, items, 3);
+        s.sort();
+        assert_eq!(s, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn weighted_golden() {
+        let items = (0..10_000u64).map(|i| (i, 1.0 + (i % 7) as f64));
+        assert_eq!(weighted_reservoir(&mut philox(), items.clone(), 6), GOLDEN_WEIGHTED_PHILOX);
+        assert_eq!(weighted_reservoir(&mut threefry(), items, 6), GOLDEN_WEIGHTED_THREEFRY);
+    }
+
+    const GOLDEN_FLOYD_PHILOX: [u64; 8] = [663, 537, 992, 679, 859, 704, 793, 881];
+    const GOLDEN_FLOYD_THREEFRY: [u64; 8] = [657, 902, 21, 88, 965, 697, 169, 166];
+    const GOLDEN_RESERVOIR_PHILOX: [u64; 6] = [510957, 594713, 242263, 429938, 766308, 884702];
+    const GOLDEN_RESERVOIR_THREEFRY: [u64; 6] = [640479, 762158, 923808, 871298, 537181, 658088];
+    const GOLDEN_WEIGHTED_PHILOX: [u64; 6] = [4524, 1642, 1104, 7814, 4162, 3364];
+    const GOLDEN_WEIGHTED_THREEFRY: [u64; 6] = [9508, 5833, 9883, 3126, 4558, 628];
+}
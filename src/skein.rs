@@ -0,0 +1,164 @@
+use rand_core::le;
+
+use super::threefish::threefish_256;
+
+const BLOCK_BYTES: usize = 32;
+
+const TYPE_CFG: u64 = 4;
+const TYPE_MSG: u64 = 48;
+const TYPE_OUT: u64 = 63;
+
+const FLAG_FIRST: u64 = 1 << 62;
+const FLAG_FINAL: u64 = 1 << 63;
+
+/// Skein-256 hash with any output length, built on Threefish-256.
+///
+/// Only the simple (non-tree, unkeyed) mode is provided.
+#[derive(Clone)]
+pub struct Skein256 {
+    chain: [u64; 4],
+    buf: [u8; BLOCK_BYTES],
+    len: usize,
+    position: u128,
+    first: bool,
+    output_bits: u64,
+}
+
+impl Skein256 {
+    pub fn new(output_bits: u64) -> Self {
+        let mut config = [0u8; BLOCK_BYTES];
+        config[..4].copy_from_slice(b"SHA3");
+        config[4] = 1;
+        config[8..16].copy_from_slice(&output_bits.to_le_bytes());
+        let mut chain = [0u64; 4];
+        ubi_block(&mut chain, &config, BLOCK_BYTES as u128,
+                  TYPE_CFG << 56 | FLAG_FIRST | FLAG_FINAL);
+        Self {
+            chain,
+            buf: [0; BLOCK_BYTES],
+            len: 0,
+            position: 0,
+            first: true,
+            output_bits,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block gets the final flag, so a full buffer is only
+            // processed once more input arrives.
+            if self.len == BLOCK_BYTES {
+                self.position += BLOCK_BYTES as u128;
+                let flags = TYPE_MSG << 56 | if self.first { FLAG_FIRST } else { 0 };
+                ubi_block(&mut self.chain, &self.buf, self.position, flags);
+                self.first = false;
+                self.len = 0;
+            }
+            let n = (BLOCK_BYTES - self.len).min(data.len());
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+        }
+    }
+
+    /// Writes `(output_bits + 7) / 8` bytes of digest to `out`.
+    pub fn finalize(mut self, out: &mut [u8]) {
+        let bytes = self.output_bits.div_ceil(8) as usize;
+        assert_eq!(out.len(), bytes, "output buffer does not match output length");
+
+        for b in self.buf[self.len..].iter_mut() {
+            *b = 0;
+        }
+        self.position += self.len as u128;
+        let flags = TYPE_MSG << 56 | FLAG_FINAL | if self.first { FLAG_FIRST } else { 0 };
+        ubi_block(&mut self.chain, &self.buf, self.position, flags);
+
+        for (i, chunk) in out.chunks_mut(BLOCK_BYTES).enumerate() {
+            let mut counter = [0u8; BLOCK_BYTES];
+            counter[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            let mut chain = self.chain;
+            ubi_block(&mut chain, &counter, 8, TYPE_OUT << 56 | FLAG_FIRST | FLAG_FINAL);
+            let mut bytes = [0u8; BLOCK_BYTES];
+            for (b, w) in bytes.chunks_mut(8).zip(chain.iter()) {
+                b.copy_from_slice(&w.to_le_bytes());
+            }
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// Skein-256 of `data`, with output length `8 * out.len()` bits.
+pub fn skein_256(data: &[u8], out: &mut [u8]) {
+    let mut h = Skein256::new(8 * out.len() as u64);
+    h.update(data);
+    h.finalize(out);
+}
+
+/// One UBI step: `chain = Threefish(chain, tweak, m) ^ m`.
+fn ubi_block(chain: &mut [u64; 4], block: &[u8; BLOCK_BYTES], position: u128, flags: u64) {
+    let mut m = [0u64; 4];
+    le::read_u64_into(block, &mut m);
+    let tweak = [position as u64, (position >> 64) as u64 | flags];
+    let x = threefish_256(chain, &tweak, &m);
+    for i in 0..4 {
+        chain[i] = x[i] ^ m[i];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{skein_256, Skein256};
+
+    const DIGEST_1: [u8; 32] = [
+        0x0B, 0x98, 0xDC, 0xD1, 0x98, 0xEA, 0x0E, 0x50, 0xA7, 0xA2, 0x44, 0xC4, 0x44, 0xE2, 0x5C, 0x23,
+        0xDA, 0x30, 0xC1, 0x0F, 0xC9, 0xA1, 0xF2, 0x70, 0xA6, 0x63, 0x7F, 0x1F, 0x34, 0xE6, 0x7E, 0xD2,
+    ];
+    const DIGEST_32: [u8; 32] = [
+        0x8D, 0x0F, 0xA4, 0xEF, 0x77, 0x7F, 0xD7, 0x59, 0xDF, 0xD4, 0x04, 0x4E, 0x6F, 0x6A, 0x5A, 0xC3,
+        0xC7, 0x74, 0xAE, 0xC9, 0x43, 0xDC, 0xFC, 0x07, 0x92, 0x7B, 0x72, 0x3B, 0x5D, 0xBF, 0x40, 0x8B,
+    ];
+    const DIGEST_64: [u8; 32] = [
+        0xDF, 0x28, 0xE9, 0x16, 0x63, 0x0D, 0x0B, 0x44, 0xC4, 0xA8, 0x49, 0xDC, 0x9A, 0x02, 0xF0, 0x7A,
+        0x07, 0xCB, 0x30, 0xF7, 0x32, 0x31, 0x82, 0x56, 0xB1, 0x5D, 0x86, 0x5A, 0xC4, 0xAE, 0x16, 0x2F,
+    ];
+
+    fn descending(n: usize) -> Vec<u8> {
+        (0..n).map(|i| 0xff - i as u8).collect()
+    }
+
+    #[test]
+    fn skein_256_256_vectors() {
+        let mut out = [0u8; 32];
+        skein_256(&descending(1), &mut out);
+        assert_eq!(out, DIGEST_1);
+        skein_256(&descending(32), &mut out);
+        assert_eq!(out, DIGEST_32);
+        skein_256(&descending(64), &mut out);
+        assert_eq!(out, DIGEST_64);
+    }
+
+    #[test]
+    fn incremental() {
+        let data: Vec<u8> = (0..200).map(|i| (i * 7) as u8).collect();
+        let mut expected = [0u8; 48];
+        skein_256(&data, &mut expected);
+        for split in [0, 1, 31, 32, 33, 64, 150, 200].iter() {
+            let mut h = Skein256::new(384);
+            h.update(&data[..*split]);
+            h.update(&[]);
+            h.update(&data[*split..]);
+            let mut out = [0u8; 48];
+            h.finalize(&mut out);
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn output_length_is_hashed() {
+        let mut short = [0u8; 16];
+        let mut long = [0u8; 32];
+        skein_256(b"key material", &mut short);
+        skein_256(b"key material", &mut long);
+        assert!(short[..] != long[..16]);
+    }
+}
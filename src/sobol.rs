@@ -0,0 +1,452 @@
+//! Sobol low-discrepancy sequences with optional Owen scrambling.
+//!
+//! Direction numbers are the leading dimensions of Joe and Kuo's
+//! `new-joe-kuo-6.21201` table. Only the first `MAX_DIMENSIONS` (21) are
+//! built in; `Sobol::from_joe_kuo` reads the full published table, or any
+//! other in the same format, for up to 21201 dimensions. Points are indexed
+//! directly rather than in Gray-code order, so any point can be computed
+//! alone.
+//!
+//! Nested uniform (Owen) scrambling flips bit `31 - l` of a coordinate when
+//! a keyed hash of its `l` leading bits, the level `l` and the dimension is
+//! odd, so every dimension has its own scramble and each key gives an
+//! independent randomized replicate. Scrambling preserves the net structure
+//! of the sequence and makes each point uniform on the unit cube; the bits
+//! below the 32 of the sequence are filled by one more hash.
+
+use std::error;
+use std::fmt;
+
+use super::philox::{philox_4x32, Array2x32};
+use super::threefry::{threefry_2x64, Array2x64};
+use super::u01::u01_f64;
+
+/// Degree `s`, interior polynomial coefficients `a` and initial direction
+/// numbers `m` of the primitive polynomials for dimensions 2 onwards.
+const JOE_KUO: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+pub const MAX_DIMENSIONS: usize = JOE_KUO.len() + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SobolError {
+    /// More dimensions were requested than the direction numbers cover.
+    TooManyDimensions { requested: usize, max: usize },
+    /// A point buffer has the wrong length.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A line of a direction number table is malformed.
+    InvalidTable { line: usize },
+}
+
+impl fmt::Display for SobolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SobolError::TooManyDimensions { requested, max } =>
+                write!(f, "{} dimensions requested, at most {} are available", requested, max),
+            SobolError::DimensionMismatch { expected, found } =>
+                write!(f, "point has {} coordinates, sequence has {} dimensions", found, expected),
+            SobolError::InvalidTable { line } =>
+                write!(f, "line {} of the direction number table is malformed", line),
+        }
+    }
+}
+
+impl error::Error for SobolError {}
+
+/// Keyed hash used for the scrambling flips.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scrambler {
+    Philox4x32(Array2x32),
+    ThreeFry2x64(Array2x64),
+}
+
+impl Scrambler {
+    #[inline]
+    fn hash(&self, dim: u32, level: u32, prefix: u32) -> u64 {
+        match *self {
+            Scrambler::Philox4x32(key) => {
+                let x = philox_4x32([prefix, level, dim, 0], key);
+                (x[1] as u64) << 32 | x[0] as u64
+            }
+            Scrambler::ThreeFry2x64(key) => {
+                let mut x = [0u64; 2];
+                threefry_2x64([(level as u64) << 32 | prefix as u64, dim as u64], key, &mut x);
+                x[0]
+            }
+        }
+    }
+
+    /// Owen-scrambles the 32-bit coordinate `x` of dimension `dim`.
+    fn scramble(&self, dim: u32, x: u32) -> u32 {
+        let mut flips = 0;
+        for level in 0..32 {
+            let prefix = if level == 0 { 0 } else { x >> (32 - level) };
+            flips |= ((self.hash(dim, level, prefix) & 1) as u32) << (31 - level);
+        }
+        x ^ flips
+    }
+}
+
+fn first_directions() -> [u32; 32] {
+    let mut v = [0u32; 32];
+    for (k, v) in v.iter_mut().enumerate() {
+        *v = 1 << (31 - k);
+    }
+    v
+}
+
+/// Direction numbers of the primitive polynomial of degree `s` with
+/// interior coefficients `a`, from the initial numbers `m`.
+fn expand_directions(s: u32, a: u32, m: &[u32]) -> [u32; 32] {
+    let s = s as usize;
+    let mut v = [0u32; 32];
+    for k in 0..32 {
+        v[k] = if k < s {
+            m[k] << (31 - k)
+        } else {
+            let mut x = v[k - s] ^ (v[k - s] >> s);
+            for j in 1..s {
+                if (a >> (s - 1 - j)) & 1 == 1 {
+                    x ^= v[k - j];
+                }
+            }
+            x
+        };
+    }
+    v
+}
+
+/// Whether `(s, a, m)` has the shape of a table entry: `s` initial numbers,
+/// `m_k` odd and below `2^k`. Primitivity of the polynomial is not checked.
+fn valid_entry(s: u32, a: u32, m: &[u32]) -> bool {
+    (1..32).contains(&s) && a < 1 << (s - 1) && m.len() == s as usize
+        && m.iter().enumerate().all(|(k, &mk)| mk & 1 == 1 && mk < 2 << k)
+}
+
+#[derive(Clone, Debug)]
+pub struct Sobol {
+    directions: Vec<[u32; 32]>,
+    scrambler: Option<Scrambler>,
+}
+
+impl Sobol {
+    /// The unscrambled sequence in `dims` dimensions.
+    pub fn new(dims: usize) -> Result<Self, SobolError> {
+        if dims > MAX_DIMENSIONS {
+            return Err(SobolError::TooManyDimensions { requested: dims, max: MAX_DIMENSIONS });
+        }
+        let mut directions = Vec::with_capacity(dims);
+        for d in 0..dims {
+            directions.push(if d == 0 {
+                first_directions()
+            } else {
+                let (s, a, m) = JOE_KUO[d - 1];
+                expand_directions(s, a, m)
+            });
+        }
+        Ok(Self { directions, scrambler: None })
+    }
+
+    /// The unscrambled sequence in `dims` dimensions, with direction numbers
+    /// read from `table` in the format of Joe and Kuo's files: an optional
+    /// header line, then `d s a m_1 ... m_s` for dimensions `d = 2, 3, ...`.
+    /// Lines past the ones needed are not read.
+    pub fn from_joe_kuo(table: &str, dims: usize) -> Result<Self, SobolError> {
+        let mut directions = Vec::with_capacity(dims);
+        if dims > 0 {
+            directions.push(first_directions());
+        }
+        let mut lines = table.lines().enumerate().filter(|&(i, l)| match l.split_whitespace().next() {
+            None => false,
+            // the header is the only line that may not start with a number
+            Some(t) => i > 0 || t.parse::<u32>().is_ok(),
+        });
+        while directions.len() < dims {
+            let (i, line) = match lines.next() {
+                Some(l) => l,
+                None => return Err(SobolError::TooManyDimensions {
+                    requested: dims, max: directions.len() }),
+            };
+            let words: Result<Vec<u32>, _> = line.split_whitespace().map(|t| t.parse()).collect();
+            let words = words.map_err(|_| SobolError::InvalidTable { line: i + 1 })?;
+            if words.len() < 3 || words[0] as usize != directions.len() + 1
+                || !valid_entry(words[1], words[2], &words[3..]) {
+                return Err(SobolError::InvalidTable { line: i + 1 });
+            }
+            directions.push(expand_directions(words[1], words[2], &words[3..]));
+        }
+        Ok(Self { directions, scrambler: None })
+    }
+
+    /// Owen-scrambles the sequence with `scrambler`.
+    pub fn scrambled(self, scrambler: Scrambler) -> Self {
+        Self { scrambler: Some(scrambler), ..self }
+    }
+
+    pub fn dims(&self) -> usize {
+        self.directions.len()
+    }
+
+    /// Coordinate `dim` of point `index` as a 32-bit fraction.
+    pub fn coordinate_u32(&self, index: u32, dim: usize) -> u32 {
+        let v = &self.directions[dim];
+        let mut x = 0;
+        let mut i = index;
+        let mut k = 0;
+        while i != 0 {
+            if i & 1 == 1 {
+                x ^= v[k];
+            }
+            i >>= 1;
+            k += 1;
+        }
+        match self.scrambler {
+            Some(s) => s.scramble(dim as u32, x),
+            None => x,
+        }
+    }
+
+    /// Coordinate `dim` of point `index` in `[0, 1)`.
+    pub fn coordinate(&self, index: u32, dim: usize) -> f64 {
+        let x = self.coordinate_u32(index, dim);
+        match self.scrambler {
+            // uniform within the finest box of the scrambled coordinate
+            Some(s) => u01_f64((x as u64) << 32 | s.hash(dim as u32, 32, x) >> 32),
+            None => x as f64 / 4_294_967_296.0,
+        }
+    }
+
+    /// Writes point `index` to `out`, which must have one slot per dimension.
+    pub fn point(&self, index: u32, out: &mut [f64]) -> Result<(), SobolError> {
+        if out.len() != self.dims() {
+            return Err(SobolError::DimensionMismatch { expected: self.dims(), found: out.len() });
+        }
+        for (d, x) in out.iter_mut().enumerate() {
+            *x = self.coordinate(index, d);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sobol, Scrambler, SobolError, JOE_KUO, MAX_DIMENSIONS, valid_entry};
+    use distributions::ks_test;
+    use philox::Philox4x64;
+    use rng::Philox4x64Rng;
+    use rand_core::RngCore;
+    use u01::u01_f64;
+
+    fn scramblers() -> [Scrambler; 2] {
+        [Scrambler::Philox4x32([0x50b0, 0x1]), Scrambler::ThreeFry2x64([7, 11])]
+    }
+
+    /// Multiplicative order of `x` modulo `poly` over GF(2).
+    fn order(poly: u32, s: u32) -> u32 {
+        let mut x = 1;
+        for k in 1..1 << s {
+            x <<= 1;
+            if x >> s & 1 == 1 {
+                x ^= poly;
+            }
+            if x == 1 {
+                return k;
+            }
+        }
+        0
+    }
+
+    #[test]
+    fn direction_numbers() {
+        for &(s, a, m) in JOE_KUO.iter() {
+            assert_eq!(order(1 << s | a << 1 | 1, s), (1 << s) - 1, "not primitive: {} {}", s, a);
+            assert!(valid_entry(s, a, m));
+        }
+    }
+
+    fn table() -> String {
+        let mut t = String::from("d       s       a       m_i\n");
+        for (d, &(s, a, m)) in JOE_KUO.iter().enumerate() {
+            let m: Vec<String> = m.iter().map(|x| x.to_string()).collect();
+            t += &format!("{}\t{}\t{}\t{}\n", d + 2, s, a, m.join(" "));
+        }
+        t
+    }
+
+    #[test]
+    fn from_table() {
+        let t = table();
+        let built_in = Sobol::new(MAX_DIMENSIONS).unwrap();
+        let read = Sobol::from_joe_kuo(&t, MAX_DIMENSIONS).unwrap();
+        for i in 0..100 {
+            for d in 0..MAX_DIMENSIONS {
+                assert_eq!(read.coordinate_u32(i, d), built_in.coordinate_u32(i, d));
+            }
+        }
+        assert_eq!(Sobol::from_joe_kuo(&t[t.find('\n').unwrap() + 1..], 5).unwrap().dims(), 5);
+        assert_eq!(Sobol::from_joe_kuo("", 1).unwrap().dims(), 1);
+        assert_eq!(Sobol::from_joe_kuo(&t, 30).unwrap_err(),
+                   SobolError::TooManyDimensions { requested: 30, max: 21 });
+
+        // only the lines that are needed are read
+        let bad = |line: &str| format!("{}{}\n", t, line);
+        assert!(Sobol::from_joe_kuo(&bad("x"), MAX_DIMENSIONS).is_ok());
+        for line in ["22 3 1 1 3", "22 3 1 1 3 2", "22 3 1 1 3 9", "22 3 4 1 3 1", "23 3 1 1 3 1",
+                     "22 3 1 1 3 x", "22 3"].iter() {
+            assert_eq!(Sobol::from_joe_kuo(&bad(line), MAX_DIMENSIONS + 1).unwrap_err(),
+                       SobolError::InvalidTable { line: 22 }, "{}", line);
+        }
+        assert!(Sobol::from_joe_kuo(&bad("22 3 1 1 3 1"), MAX_DIMENSIONS + 1).is_ok());
+    }
+
+    #[test]
+    fn leading_points() {
+        let s = Sobol::new(3).unwrap();
+        let p: Vec<[f64; 3]> = (0..4).map(|i| {
+            let mut x = [0.0; 3];
+            s.point(i, &mut x).unwrap();
+            x
+        }).collect();
+        assert_eq!(p, [[0.0, 0.0, 0.0], [0.5, 0.5, 0.5], [0.25, 0.75, 0.75], [0.75, 0.25, 0.25]]);
+        assert_eq!(Sobol::new(MAX_DIMENSIONS + 1).unwrap_err(),
+                   SobolError::TooManyDimensions { requested: 22, max: 21 });
+        assert!(s.point(0, &mut [0.0; 2]).is_err());
+    }
+
+    /// Checks that the first `2^m` points of dimensions `a` and `b` form a
+    /// (0, m, 2)-net: every elementary box of area `2^-m` holds one point.
+    fn assert_net(s: &Sobol, a: usize, b: usize, m: u32) {
+        for bits in 0..m + 1 {
+            let mut seen = vec![false; 1 << m];
+            for i in 0..1u32 << m {
+                let x = s.coordinate_u32(i, a) as u64 >> (32 - bits);
+                let y = s.coordinate_u32(i, b) as u64 >> (32 - (m - bits));
+                let cell = (x << (m - bits) | y) as usize;
+                assert!(!seen[cell], "dims {} {} split {} point {}", a, b, bits, i);
+                seen[cell] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn nets() {
+        let plain = Sobol::new(4).unwrap();
+        assert_net(&plain, 0, 1, 10);
+        for &sc in scramblers().iter() {
+            let s = Sobol::new(MAX_DIMENSIONS).unwrap().scrambled(sc);
+            assert_net(&s, 0, 1, 10);
+            // every one-dimensional projection is stratified
+            for d in 0..MAX_DIMENSIONS {
+                let mut seen = vec![false; 256];
+                for i in 0..256 {
+                    let cell = (s.coordinate(i, d) * 256.0) as usize;
+                    assert!(!seen[cell]);
+                    seen[cell] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scrambled_uniform() {
+        for &sc in scramblers().iter() {
+            let s = Sobol::new(8).unwrap().scrambled(sc);
+            ks_test((0..4000).map(|i| s.coordinate(i, 7)).collect(), |x| x);
+            // across replicates a single point is uniform too
+            let samples = (0..4000u64).map(|k| {
+                let s = Sobol::new(3).unwrap().scrambled(Scrambler::ThreeFry2x64([k, 0]));
+                s.coordinate(5, 2)
+            }).collect();
+            ks_test(samples, |x| x);
+        }
+    }
+
+    /// Warnock's formula for the L2 star discrepancy.
+    fn l2_star(points: &[Vec<f64>]) -> f64 {
+        let n = points.len() as f64;
+        let d = points[0].len() as i32;
+        let mut cross = 0.0;
+        let mut pairs = 0.0;
+        for p in points {
+            cross += p.iter().map(|&x| 1.0 - x * x).product::<f64>();
+            for q in points {
+                pairs += p.iter().zip(q).map(|(&x, &y)| 1.0 - x.max(y)).product::<f64>();
+            }
+        }
+        (3f64.powi(-d) - 2f64.powi(1 - d) / n * cross + pairs / (n * n)).sqrt()
+    }
+
+    #[test]
+    fn discrepancy() {
+        let n = 512;
+        let d = 6;
+        let mut rng = Philox4x64Rng::from(Philox4x64::from_seed([5; 16]));
+        let random: Vec<Vec<f64>> = (0..n)
+            .map(|_| (0..d).map(|_| u01_f64(rng.next_u64())).collect())
+            .collect();
+        let random = l2_star(&random);
+        for &sc in scramblers().iter() {
+            let s = Sobol::new(d).unwrap().scrambled(sc);
+            let points: Vec<Vec<f64>> = (0..n as u32).map(|i| {
+                let mut x = vec![0.0; d];
+                s.point(i, &mut x).unwrap();
+                x
+            }).collect();
+            let sobol = l2_star(&points);
+            assert!(sobol < 0.5 * random, "{} {}", sobol, random);
+        }
+    }
+
+    #[test]
+    fn randomized_integration() {
+        // replicates give an unbiased estimate of a smooth integral with far
+        // less spread than plain Monte Carlo at the same size
+        let f = |x: &[f64]| x.iter().map(|&x| 2.0 * x).product::<f64>();
+        let (reps, n, d) = (40, 256, 5);
+        let mut estimates = Vec::new();
+        for k in 0..reps {
+            let s = Sobol::new(d).unwrap().scrambled(Scrambler::Philox4x32([k, 99]));
+            let mut x = vec![0.0; d];
+            let sum: f64 = (0..n).map(|i| {
+                s.point(i, &mut x).unwrap();
+                f(&x)
+            }).sum();
+            estimates.push(sum / n as f64);
+        }
+        let mean = estimates.iter().sum::<f64>() / reps as f64;
+        let var = estimates.iter().map(|e| (e - mean) * (e - mean)).sum::<f64>() / (reps - 1) as f64;
+        // Monte Carlo variance would be ((4/3)^5 - 1) / 256, about 0.012
+        assert!(var < 0.001, "{}", var);
+        assert!((mean - 1.0).abs() < 4.0 * (var / reps as f64).sqrt() + 1e-3, "{}", mean);
+    }
+
+    #[test]
+    fn golden_values() {
+        let s = Sobol::new(2).unwrap().scrambled(Scrambler::Philox4x32([1, 2]));
+        let v: Vec<u32> = (0..4).map(|i| s.coordinate_u32(i, 1)).collect();
+        assert_eq!(v, [3112729993, 391417347, 2090379464, 3680259548]);
+        let s = Sobol::new(2).unwrap().scrambled(Scrambler::ThreeFry2x64([1, 2]));
+        let v: Vec<f64> = (0..3).map(|i| s.coordinate(i, 0)).collect();
+        assert_eq!(v, [0.646621782596576, 0.1268422766477132, 0.8370697795403768]);
+    }
+}
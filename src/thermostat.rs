@@ -0,0 +1,156 @@
+//! Random forces for Langevin thermostats.
+//!
+//! Atom `id` at step `n` gets three independent standard normals from one
+//! block: `philox_4x32` at counter `[id, n]` for single precision and
+//! `philox_4x64` at counter `[id, n, 0, 0]` for double, both under the seed,
+//! with Box–Muller on the word pairs and the fourth normal discarded. The
+//! noise depends only on the global atom id, so it does not change with the
+//! domain decomposition, the order of atoms or the number of ranks.
+
+use std::f32::consts::PI as PI_F32;
+use std::f64::consts::PI;
+
+use super::philox::{philox_4x32, philox_4x64};
+use super::u01::{u01_f32, u01_f64, u01_open_f32, u01_open_f64};
+
+/// Standard deviation `sqrt(2 γ kT dt / m)` of the velocity kick over a
+/// step of `dt` with friction `gamma`.
+pub fn kick_scale(gamma: f64, kt: f64, mass: f64, dt: f64) -> f64 {
+    (2.0 * gamma * kt * dt / mass).sqrt()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LangevinNoise {
+    seed: u64,
+}
+
+macro_rules! fill {
+    ($normals: ident, $aos: ident, $soa: ident, $t: ty) => {
+        /// Writes `scale[i]` times the normals of atom `ids[i]` to `out[i]`.
+        pub fn $aos(&self, step: u64, ids: &[u64], scale: &[$t], out: &mut [[$t; 3]]) {
+            assert!(ids.len() == scale.len() && ids.len() == out.len(), "buffer lengths differ");
+            for ((&id, &s), v) in ids.iter().zip(scale).zip(out.iter_mut()) {
+                let n = self.$normals(step, id);
+                *v = [s * n[0], s * n[1], s * n[2]];
+            }
+        }
+
+        /// Like the array-of-structures fill, to separate component arrays.
+        pub fn $soa(&self, step: u64, ids: &[u64], scale: &[$t],
+                    x: &mut [$t], y: &mut [$t], z: &mut [$t]) {
+            let n = ids.len();
+            assert!(scale.len() == n && x.len() == n && y.len() == n && z.len() == n,
+                    "buffer lengths differ");
+            for i in 0..n {
+                let v = self.$normals(step, ids[i]);
+                x[i] = scale[i] * v[0];
+                y[i] = scale[i] * v[1];
+                z[i] = scale[i] * v[2];
+            }
+        }
+    }
+}
+
+impl LangevinNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Standard normals of atom `id` at `step`, in single precision.
+    pub fn normals_f32(&self, step: u64, id: u64) -> [f32; 3] {
+        let ctr = [id as u32, (id >> 32) as u32, step as u32, (step >> 32) as u32];
+        let w = philox_4x32(ctr, [self.seed as u32, (self.seed >> 32) as u32]);
+        let r = (-2.0 * u01_open_f32(w[0]).ln()).sqrt();
+        let theta = 2.0 * PI_F32 * u01_f32(w[1]);
+        let s = (-2.0 * u01_open_f32(w[2]).ln()).sqrt();
+        let phi = 2.0 * PI_F32 * u01_f32(w[3]);
+        [r * theta.cos(), r * theta.sin(), s * phi.cos()]
+    }
+
+    /// Standard normals of atom `id` at `step`, in double precision.
+    pub fn normals_f64(&self, step: u64, id: u64) -> [f64; 3] {
+        let w = philox_4x64([id, step, 0, 0], [self.seed, 0]);
+        let r = (-2.0 * u01_open_f64(w[0]).ln()).sqrt();
+        let theta = 2.0 * PI * u01_f64(w[1]);
+        let s = (-2.0 * u01_open_f64(w[2]).ln()).sqrt();
+        let phi = 2.0 * PI * u01_f64(w[3]);
+        [r * theta.cos(), r * theta.sin(), s * phi.cos()]
+    }
+
+    fill!(normals_f32, fill_aos_f32, fill_soa_f32, f32);
+    fill!(normals_f64, fill_aos_f64, fill_soa_f64, f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LangevinNoise, kick_scale};
+    use distributions::{ks_test, normal_cdf};
+
+    #[test]
+    fn normal_components() {
+        let noise = LangevinNoise::new(0x7e57);
+        for c in 0..3 {
+            ks_test((0..6000).map(|id| noise.normals_f64(12, id)[c]).collect(), normal_cdf);
+            ks_test((0..6000).map(|id| noise.normals_f32(12, id)[c] as f64).collect(), normal_cdf);
+        }
+        // components, atoms and steps are uncorrelated
+        let n = 50_000;
+        let (mut xy, mut xz, mut steps) = (0.0, 0.0, 0.0);
+        for id in 0..n {
+            let v = noise.normals_f64(3, id);
+            xy += v[0] * v[1];
+            xz += v[0] * v[2];
+            steps += v[2] * noise.normals_f64(4, id)[2];
+        }
+        for &c in [xy, xz, steps].iter() {
+            assert!((c / n as f64).abs() < 0.02, "{}", c / n as f64);
+        }
+    }
+
+    #[test]
+    fn decomposition() {
+        let noise = LangevinNoise::new(99);
+        let ids: Vec<u64> = (0..40).map(|i| i * 7919 % 1000).collect();
+        let scale = vec![0.5; 40];
+        let mut whole = vec![[0.0; 3]; 40];
+        noise.fill_aos_f64(8, &ids, &scale, &mut whole);
+
+        // two ranks each own every other atom, in reverse order
+        for rank in 0..2 {
+            let mine: Vec<usize> = (0..40).filter(|i| i % 2 == rank).rev().collect();
+            let local_ids: Vec<u64> = mine.iter().map(|&i| ids[i]).collect();
+            let mut local = vec![[0.0; 3]; mine.len()];
+            noise.fill_aos_f64(8, &local_ids, &scale[..mine.len()], &mut local);
+            for (k, &i) in mine.iter().enumerate() {
+                assert_eq!(local[k], whole[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_and_scale() {
+        let noise = LangevinNoise::new(5);
+        let ids = [3, 1, 4, 1, 5];
+        let scale = [1.0f32, 2.0, 0.5, 1.0, 3.0];
+        let mut aos = [[0.0f32; 3]; 5];
+        noise.fill_aos_f32(2, &ids, &scale, &mut aos);
+        let (mut x, mut y, mut z) = ([0.0f32; 5], [0.0f32; 5], [0.0f32; 5]);
+        noise.fill_soa_f32(2, &ids, &scale, &mut x, &mut y, &mut z);
+        for i in 0..5 {
+            assert_eq!(aos[i], [x[i], y[i], z[i]]);
+            let n = noise.normals_f32(2, ids[i]);
+            assert_eq!(aos[i][2], scale[i] * n[2]);
+        }
+        // the same atom listed twice gets the same kick
+        assert_eq!(aos[1], [2.0 * aos[3][0], 2.0 * aos[3][1], 2.0 * aos[3][2]]);
+        assert!((kick_scale(1.0, 2.5, 4.0, 0.002) - 0.05).abs() < 1e-15);
+    }
+
+    #[test]
+    fn golden_values() {
+        let noise = LangevinNoise::new(1);
+        assert_eq!(noise.normals_f64(0, 0),
+                   [-0.43867514615075165, -0.5163706935149391, 0.12350187127041505]);
+        assert_eq!(noise.normals_f32(10, 20), [-0.39211276, -0.7268357, -1.6677969]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Threefish-256 and Threefish-512 block ciphers, with decryption.
+//!
+//! Only the key parity constant comes from `threefry`. Its ThreeFry-2x64
+//! hardcodes the two-word rotation schedule, 20 rounds and a key injection
+//! without tweak words; Threefish needs four- and eight-word rotations and
+//! permutations, 72 rounds and a tweak in every injection, so it keeps its
+//! own tables and schedule.
+
+use super::threefry::SKEIN_PARITY;
+
+pub type Array4x64 = [u64; 4];
+pub type Array8x64 = [u64; 8];
+pub type Tweak = [u64; 2];
+
+/// Threefish-256 rotation constants, shared with ThreeFry-4x64.
+const R_256: [[u32; 2]; 8] = [
+    [14, 16], [52, 57], [23, 40], [ 5, 37],
+    [25, 33], [46, 12], [58, 22], [32, 32],
+];
+
+/// Threefish-256 word permutation applied after each round.
+const PI_256: [usize; 4] = [0, 3, 2, 1];
+
+const R_512: [[u32; 4]; 8] = [
+    [46, 36, 19, 37], [33, 27, 14, 42], [17, 49, 36, 39], [44,  9, 54, 56],
+    [39, 30, 34, 24], [13, 50, 10, 17], [25, 29, 39, 43], [ 8, 35, 56, 22],
+];
+
+const PI_512: [usize; 8] = [2, 1, 4, 7, 6, 5, 0, 3];
+
+const ROUNDS: usize = 72;
+
+/// Expands a key into the `n + 1` words of the Threefish key schedule.
+macro_rules! key_schedule {
+    ($key: expr, $n: expr) => {{
+        let mut ks = [0u64; $n + 1];
+        ks[$n] = SKEIN_PARITY;
+        for i in 0..$n {
+            ks[i] = $key[i];
+            ks[$n] ^= $key[i];
+        }
+        ks
+    }}
+}
+
+macro_rules! threefish {
+    ($cipher: ident, $encrypt: ident, $decrypt: ident, $arr: ty, $n: expr, $r: ident, $pi: ident) => {
+        pub fn $encrypt(key: &$arr, tweak: &Tweak, block: &$arr) -> $arr {
+            $cipher::new(key).encrypt(tweak, block)
+        }
+
+        pub fn $decrypt(key: &$arr, tweak: &Tweak, block: &$arr) -> $arr {
+            $cipher::new(key).decrypt(tweak, block)
+        }
+
+        /// Threefish block cipher with the key schedule expanded once.
+        #[derive(Clone)]
+        pub struct $cipher {
+            ks: [u64; $n + 1],
+        }
+
+        impl $cipher {
+            pub fn new(key: &$arr) -> Self {
+                Self { ks: key_schedule!(key, $n) }
+            }
+
+            pub fn encrypt(&self, tweak: &Tweak, block: &$arr) -> $arr {
+                let ts = [tweak[0], tweak[1], tweak[0] ^ tweak[1]];
+                let mut x = *block;
+                for d in 0..ROUNDS {
+                    if d % 4 == 0 {
+                        inject!(x, self.ks, ts, $n, d / 4, wrapping_add);
+                    }
+                    let mut y = [0u64; $n];
+                    for j in 0..$n / 2 {
+                        let a = x[2 * j].wrapping_add(x[2 * j + 1]);
+                        y[2 * j] = a;
+                        y[2 * j + 1] = x[2 * j + 1].rotate_left($r[d % 8][j]) ^ a;
+                    }
+                    for i in 0..$n {
+                        x[i] = y[$pi[i]];
+                    }
+                }
+                inject!(x, self.ks, ts, $n, ROUNDS / 4, wrapping_add);
+                x
+            }
+
+            pub fn decrypt(&self, tweak: &Tweak, block: &$arr) -> $arr {
+                let ts = [tweak[0], tweak[1], tweak[0] ^ tweak[1]];
+                let mut x = *block;
+                inject!(x, self.ks, ts, $n, ROUNDS / 4, wrapping_sub);
+                for d in (0..ROUNDS).rev() {
+                    let mut y = [0u64; $n];
+                    for i in 0..$n {
+                        y[$pi[i]] = x[i];
+                    }
+                    for j in 0..$n / 2 {
+                        let b = (y[2 * j + 1] ^ y[2 * j]).rotate_right($r[d % 8][j]);
+                        x[2 * j] = y[2 * j].wrapping_sub(b);
+                        x[2 * j + 1] = b;
+                    }
+                    if d % 4 == 0 {
+                        inject!(x, self.ks, ts, $n, d / 4, wrapping_sub);
+                    }
+                }
+                x
+            }
+        }
+    }
+}
+
+/// Adds (or subtracts) subkey `s` to the state.
+macro_rules! inject {
+    ($x: expr, $ks: expr, $ts: expr, $n: expr, $s: expr, $op: ident) => {{
+        let s = $s;
+        for i in 0..$n {
+            let mut k = $ks[(s + i) % ($n + 1)];
+            if i == $n - 3 {
+                k = k.wrapping_add($ts[s % 3]);
+            } else if i == $n - 2 {
+                k = k.wrapping_add($ts[(s + 1) % 3]);
+            } else if i == $n - 1 {
+                k = k.wrapping_add(s as u64);
+            }
+            $x[i] = $x[i].$op(k);
+        }
+    }}
+}
+
+threefish!(Threefish256, threefish_256, threefish_256_decrypt, Array4x64, 4, R_256, PI_256);
+threefish!(Threefish512, threefish_512, threefish_512_decrypt, Array8x64, 8, R_512, PI_512);
+
+#[cfg(test)]
+mod tests {
+    use super::{threefish_256, threefish_256_decrypt, threefish_512, threefish_512_decrypt};
+    use super::{Threefish256, Threefish512};
+
+    #[test]
+    fn threefish_256_zero() {
+        let x = threefish_256(&[0; 4], &[0; 2], &[0; 4]);
+        assert_eq!(x, [0x94EEEA8B1F2ADA84, 0xADF103313EAE6670,
+                       0x952419A1F4B16D53, 0xD83F13E63C9F6B11]);
+    }
+
+    #[test]
+    fn threefish_256_counting() {
+        let key = [0x1716151413121110, 0x1F1E1D1C1B1A1918,
+                   0x2726252423222120, 0x2F2E2D2C2B2A2928];
+        let tweak = [0x0706050403020100, 0x0F0E0D0C0B0A0908];
+        let pt = [0xF8F9FAFBFCFDFEFF, 0xF0F1F2F3F4F5F6F7,
+                  0xE8E9EAEBECEDEEEF, 0xE0E1E2E3E4E5E6E7];
+        assert_eq!(threefish_256(&key, &tweak, &pt),
+                   [0xDF8FEA0EFF91D0E0, 0xD50AD82EE69281C9,
+                    0x76F48D58085D869D, 0xDF975E95B5567065]);
+    }
+
+    #[test]
+    fn threefish_512_zero() {
+        let x = threefish_512(&[0; 8], &[0; 2], &[0; 8]);
+        assert_eq!(x, [0xBC2560EFC6BBA2B1, 0xE3361F162238EB40,
+                       0xFB8631EE0ABBD175, 0x7B9479D4C5479ED1,
+                       0xCFF0356E58F8C27B, 0xB1B7B08430F0E7F7,
+                       0xE9A380A56139ABF1, 0xBE7B6D4AA11EB47E]);
+    }
+
+    #[test]
+    fn threefish_512_counting() {
+        let key = [0x1716151413121110, 0x1F1E1D1C1B1A1918,
+                   0x2726252423222120, 0x2F2E2D2C2B2A2928,
+                   0x3736353433323130, 0x3F3E3D3C3B3A3938,
+                   0x4746454443424140, 0x4F4E4D4C4B4A4948];
+        let tweak = [0x0706050403020100, 0x0F0E0D0C0B0A0908];
+        let pt = [0xF8F9FAFBFCFDFEFF, 0xF0F1F2F3F4F5F6F7,
+                  0xE8E9EAEBECEDEEEF, 0xE0E1E2E3E4E5E6E7,
+                  0xD8D9DADBDCDDDEDF, 0xD0D1D2D3D4D5D6D7,
+                  0xC8C9CACBCCCDCECF, 0xC0C1C2C3C4C5C6C7];
+        let ct = [0x2C5AD426964304E3, 0x9A2436D6D8CA01B4,
+                  0xDD456DB00E333863, 0x794725970EB9368B,
+                  0x043546998D0A2A27, 0x25A7C918EA204478,
+                  0x346201A1FEDF11AF, 0x3DAF1C5C3D672789];
+        assert_eq!(threefish_512(&key, &tweak, &pt), ct);
+        assert_eq!(threefish_512_decrypt(&key, &tweak, &ct), pt);
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = [1, 2, 3, 4];
+        let cipher = Threefish256::new(&key);
+        for i in 0..16u64 {
+            let tweak = [i, !i];
+            let pt = [i, i * i, i << 40, u64::MAX - i];
+            let ct = cipher.encrypt(&tweak, &pt);
+            assert_eq!(ct, threefish_256(&key, &tweak, &pt));
+            assert_eq!(cipher.decrypt(&tweak, &ct), pt);
+            assert_eq!(threefish_256_decrypt(&key, &tweak, &ct), pt);
+        }
+
+        let cipher = Threefish512::new(&[9; 8]);
+        let pt = [1, 2, 3, 4, 5, 6, 7, 8];
+        let ct = cipher.encrypt(&[5, 6], &pt);
+        assert!(ct != cipher.encrypt(&[5, 7], &pt));
+        assert_eq!(cipher.decrypt(&[5, 6], &ct), pt);
+    }
+}
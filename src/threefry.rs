@@ -1,34 +1,40 @@
 use rand_core::le;
 
+use super::counter::CounterState;
+
 pub type Array2x64 = [u64; 2];
 
 #[derive(Clone)]
 pub struct ThreeFry2x64 {
-    ctr: Array2x64,
+    ctr: CounterState<Array2x64>,
     key: Array2x64,
 }
 
 impl ThreeFry2x64 {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Array2x64 {
         let mut results = [0u64; 2];
-        threefry_2x64(self.ctr, self.key, &mut results);
-        self.ctr[0] = self.ctr[0].wrapping_add(1);
-        if self.ctr[0] == 0 {
-            self.ctr[1] = self.ctr[1].wrapping_add(1);
-        }
+        threefry_2x64(self.ctr.take(), self.key, &mut results);
         results
     }
 
     pub fn from_seed(seed: [u8; 16]) -> Self {
         let mut key = [0u64; 2];
         le::read_u64_into(&seed, &mut key);
-        Self { ctr: [0,0], key: key }
+        Self { ctr: CounterState::new([0,0]), key }
     }
 }
 
+impl_counter_access!(ThreeFry2x64, Array2x64);
+impl_word_at!(ThreeFry2x64, Array2x64, u64, 2, |ctr, key| {
+    let mut x = [0u64; 2];
+    threefry_2x64(ctr, key, &mut x);
+    x
+});
+
 const SKEIN_HI: u64 = 0x1BD11BDA;
 const SKEIN_LO: u64 = 0xA9FC1A22;
-const SKEIN_PARITY: u64 = SKEIN_LO + (SKEIN_HI << 32);
+pub(crate) const SKEIN_PARITY: u64 = SKEIN_LO + (SKEIN_HI << 32);
 
 const R_64X2_0_0: u32 = 16;
 const R_64X2_1_0: u32 = 42;
@@ -65,6 +71,7 @@ macro_rules! sbox {
     }}
 }
 
+#[allow(clippy::manual_memcpy)]
 pub fn threefry_2x64(ctr: Array2x64, key: Array2x64, x: &mut Array2x64) {
     let mut ks: [u64; 3] = [0, 0, SKEIN_PARITY];
     for i in 0..2 {
@@ -111,6 +118,7 @@ mod tests {
     use super::{Array2x64, threefry_2x64};
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn exact_values() {
         let mut ctr: Array2x64 = [0,0];
         let key: Array2x64 = [SEED1_U64, SEED2_U64];
@@ -0,0 +1,48 @@
+const F32_24: f32 = 1.0 / (1u32 << 24) as f32;
+const F32_23: f32 = 1.0 / (1u32 << 23) as f32;
+const F64_53: f64 = 1.0 / (1u64 << 53) as f64;
+const F64_52: f64 = 1.0 / (1u64 << 52) as f64;
+
+/// Uniform on [0, 1) from the top 24 bits of `x`.
+#[inline]
+pub fn u01_f32(x: u32) -> f32 {
+    (x >> 8) as f32 * F32_24
+}
+
+/// Uniform on [0, 1) from the top 53 bits of `x`.
+#[inline]
+pub fn u01_f64(x: u64) -> f64 {
+    (x >> 11) as f64 * F64_53
+}
+
+/// Uniform on (0, 1) from the top 23 bits of `x`; the result is never zero
+/// and can safely be passed to `ln`.
+#[inline]
+pub fn u01_open_f32(x: u32) -> f32 {
+    ((x >> 9) as f32 + 0.5) * F32_23
+}
+
+/// Uniform on (0, 1) from the top 52 bits of `x`.
+#[inline]
+pub fn u01_open_f64(x: u64) -> f64 {
+    ((x >> 12) as f64 + 0.5) * F64_52
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{u01_f32, u01_f64, u01_open_f32, u01_open_f64};
+
+    #[test]
+    fn bounds() {
+        assert_eq!(u01_f32(0), 0.0);
+        assert!(u01_f32(u32::MAX) < 1.0);
+        assert_eq!(u01_f64(0), 0.0);
+        assert!(u01_f64(u64::MAX) < 1.0);
+        assert!(u01_open_f32(0) > 0.0);
+        assert!(u01_open_f32(u32::MAX) < 1.0);
+        assert!(u01_open_f64(0) > 0.0);
+        assert!(u01_open_f64(u64::MAX) < 1.0);
+        assert_eq!(u01_f64(1 << 63), 0.5);
+        assert_eq!(u01_open_f64(1 << 63), 0.5 + 0.5 / (1u64 << 52) as f64);
+    }
+}